@@ -6,6 +6,10 @@ pub struct Attrs {
     pub len: Option<Len>,
     pub len_endian: Endian,
     pub endian: Endian,
+    // Changes to the attributes passed to a container's elements, or a map's keys and values
+    pub inner: Option<&'static Patch>,
+    pub key: Option<&'static Patch>,
+    pub value: Option<&'static Patch>,
 }
 
 impl Attrs {
@@ -14,9 +18,46 @@ impl Attrs {
             len: None,
             len_endian: Endian::Little,
             endian: Endian::Little,
+            inner: None,
+            key: None,
+            value: None,
         }
     }
 
+    // The attributes of a container's elements: its own, changed by any inner(...)
+    pub fn for_element(&self) -> Self {
+        self.patched(self.inner)
+    }
+
+    // The attributes of a map's keys: its own, changed by any key(...)
+    pub fn for_key(&self) -> Self {
+        self.patched(self.key)
+    }
+
+    // The attributes of a map's values: its own, changed by any value(...)
+    pub fn for_value(&self) -> Self {
+        self.patched(self.value)
+    }
+
+    fn patched(&self, patch: Option<&Patch>) -> Self {
+        let mut attrs = *self;
+        if let Some(patch) = patch {
+            if let Some(len) = patch.len {
+                attrs.len = len;
+            }
+            if let Some(len_endian) = patch.len_endian {
+                attrs.len_endian = len_endian;
+            }
+            if let Some(endian) = patch.endian {
+                attrs.endian = endian;
+            }
+            attrs.inner = patch.inner;
+            attrs.key = patch.key;
+            attrs.value = patch.value;
+        }
+        attrs
+    }
+
     pub fn encode_length(&self, buf: &mut dyn crate::BinWrite, len: u64) -> Result<()> {
         if self.len.is_none() {
             return Ok(());
@@ -32,6 +73,29 @@ impl Attrs {
     }
 }
 
+// The attributes inner(...), key(...) or value(...) set for one level of a nested container.
+// Those left as None are inherited from the container.
+#[derive(Debug, Copy, Clone)]
+pub struct Patch {
+    pub len: Option<Option<Len>>,
+    pub len_endian: Option<Endian>,
+    pub endian: Option<Endian>,
+    pub inner: Option<&'static Patch>,
+    pub key: Option<&'static Patch>,
+    pub value: Option<&'static Patch>,
+}
+
+impl Patch {
+    pub const NONE: Patch = Patch {
+        len: None,
+        len_endian: None,
+        endian: None,
+        inner: None,
+        key: None,
+        value: None,
+    };
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum Len {
     U8,
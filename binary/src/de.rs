@@ -1,20 +1,67 @@
 use crate::attr::Attrs;
-use crate::{BinRead, Result};
+use crate::{BinRead, BorrowRead, Result, StreamLevel, Tracked};
 
 pub trait BinDeserialize: Sized {
     fn decode_from(buf: &mut dyn BinRead, attrs: Attrs) -> Result<Self>;
 }
 
-pub fn decode_from_bytes<T>(mut buf: &[u8]) -> Result<T>
+// BinDeserializeArgs is implemented by types that need values from their parent to be decoded,
+// such as a format version; derived with #[binary(import = "...")]
+pub trait BinDeserializeArgs: Sized {
+    type Args;
+    fn decode_with(buf: &mut dyn BinRead, attrs: Attrs, args: Self::Args) -> Result<Self>;
+}
+
+// BinDeserializeTagged is the decoding counterpart of BinSerializeTagged, for a tag read elsewhere
+pub trait BinDeserializeTagged: Sized {
+    type Tag;
+    fn decode_untagged(buf: &mut dyn BinRead, attrs: Attrs, tag: Self::Tag) -> Result<Self>;
+}
+
+// BinDeserializeBorrowed is implemented by types that may borrow from the slice they are decoded
+// from, such as &[u8], &str and derived types with lifetime parameters. Every BinDeserialize type
+// implements it by copying.
+pub trait BinDeserializeBorrowed<'de>: Sized {
+    fn decode_borrowed(buf: &mut dyn BorrowRead<'de>, attrs: Attrs) -> Result<Self>;
+}
+
+impl<'de, T> BinDeserializeBorrowed<'de> for T
 where
     T: BinDeserialize,
 {
-    T::decode_from(&mut buf, Attrs::zero())
+    fn decode_borrowed(buf: &mut dyn BorrowRead<'de>, attrs: Attrs) -> Result<Self> {
+        T::decode_from(buf, attrs)
+    }
+}
+
+pub fn decode_from_bytes<'de, T>(mut buf: &'de [u8]) -> Result<T>
+where
+    T: BinDeserializeBorrowed<'de>,
+{
+    let _level = StreamLevel::enter(Some(0));
+    T::decode_borrowed(&mut Tracked::new(&mut buf), Attrs::zero())
 }
 
 pub fn decode_from_stream<T>(s: &mut dyn BinRead) -> Result<T>
 where
     T: BinDeserialize,
 {
-    T::decode_from(s, Attrs::zero())
+    let _level = StreamLevel::enter(Some(0));
+    T::decode_from(&mut Tracked::new(s), Attrs::zero())
+}
+
+pub fn decode_from_bytes_with<T>(mut buf: &[u8], args: T::Args) -> Result<T>
+where
+    T: BinDeserializeArgs,
+{
+    let _level = StreamLevel::enter(Some(0));
+    T::decode_with(&mut Tracked::new(&mut buf), Attrs::zero(), args)
+}
+
+pub fn decode_from_stream_with<T>(s: &mut dyn BinRead, args: T::Args) -> Result<T>
+where
+    T: BinDeserializeArgs,
+{
+    let _level = StreamLevel::enter(Some(0));
+    T::decode_with(&mut Tracked::new(s), Attrs::zero(), args)
 }
@@ -10,9 +10,34 @@ pub enum BinError {
     // A variant tag was parsed that did not correspond to a known enum variant.
     // The parameter indicates the invalid variant tag.
     VariantNotMatched(u64),
+    // As VariantNotMatched, for enums tagged with byte strings.
+    ByteVariantNotMatched(Vec<u8>),
+    // A variant's #[binary(tag)] field held a value that is not one of the variant's tags.
+    // The parameter names the variant.
+    TagMismatch(String),
     IntTooLarge(TryFromIntError),
     InvalidUTF8(FromUtf8Error),
     IOError(String),
+    // A conditional field was present while its condition was false, or absent while it was true.
+    // The parameter names the field.
+    ConditionMismatch(String),
+    // Padding contained a byte other than the expected fill byte.
+    // The parameter indicates the byte found.
+    InvalidPadding(u8),
+    // A value failed an assertion or validation hook. `field` names the field, or the type or
+    // variant for checks on a whole value.
+    ValidationFailed { field: String, message: String },
+    // A field's value could not be converted to or from the type it is encoded as.
+    ConversionFailed { field: String, message: String },
+    // A strict flags word had bits set that no field claims.
+    // The parameter indicates the unclaimed bits.
+    UnknownFlags(u64),
+    // A nested value did not use all of its length-prefixed buffer.
+    // The parameter indicates the number of bytes left over.
+    TrailingData(u64),
+    // A field aligned relative to the start of the stream was (de)serialized other than through
+    // the top-level functions, such as encode_to_bytes, which track the position in the stream.
+    UnknownStreamPosition,
 
     Custom(String),
 }
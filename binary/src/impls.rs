@@ -1,6 +1,11 @@
 use crate::attr::{Attrs, Endian};
-use crate::{BinDeserialize, BinError, BinFlags, BinRead, BinSerialize, BinWrite, Result};
+use crate::{
+    BinDeserialize, BinDeserializeBorrowed, BinError, BinFlags, BinRead, BinSerialize,
+    BinSerializeArgs, BinWrite, BorrowRead, Result,
+};
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryInto;
+use std::hash::Hash;
 
 impl BinSerialize for bool {
     fn encode_to(&self, buf: &mut dyn BinWrite, _attrs: Attrs) -> Result<()> {
@@ -36,11 +41,11 @@ impl BinDeserialize for u8 {
 }
 impl BinFlags for u8 {
     const ZERO: Self = 0;
-    fn has(&self, v: u64) -> bool {
-        (*self & (v as u8)) != 0
+    fn bits(&self) -> u64 {
+        u64::from(*self)
     }
-    fn set(&mut self, v: u64) {
-        *self |= v as u8
+    fn set_bits(&mut self, mask: u64, value: u64) {
+        *self = (*self & !(mask as u8)) | (value & mask) as u8
     }
 }
 
@@ -62,11 +67,11 @@ impl BinDeserialize for u16 {
 }
 impl BinFlags for u16 {
     const ZERO: Self = 0;
-    fn has(&self, v: u64) -> bool {
-        (*self & (v as u16)) != 0
+    fn bits(&self) -> u64 {
+        u64::from(*self)
     }
-    fn set(&mut self, v: u64) {
-        *self |= v as u16
+    fn set_bits(&mut self, mask: u64, value: u64) {
+        *self = (*self & !(mask as u16)) | (value & mask) as u16
     }
 }
 
@@ -105,11 +110,11 @@ impl BinDeserialize for u32 {
 }
 impl BinFlags for u32 {
     const ZERO: Self = 0;
-    fn has(&self, v: u64) -> bool {
-        (*self & (v as u32)) != 0
+    fn bits(&self) -> u64 {
+        u64::from(*self)
     }
-    fn set(&mut self, v: u64) {
-        *self |= v as u32
+    fn set_bits(&mut self, mask: u64, value: u64) {
+        *self = (*self & !(mask as u32)) | (value & mask) as u32
     }
 }
 
@@ -148,11 +153,11 @@ impl BinDeserialize for u64 {
 }
 impl BinFlags for u64 {
     const ZERO: Self = 0;
-    fn has(&self, v: u64) -> bool {
-        (*self & v) != 0
+    fn bits(&self) -> u64 {
+        *self
     }
-    fn set(&mut self, v: u64) {
-        *self |= v
+    fn set_bits(&mut self, mask: u64, value: u64) {
+        *self = (*self & !mask) | (value & mask)
     }
 }
 
@@ -215,6 +220,15 @@ where
         (*self).encode_to(buf, attrs)
     }
 }
+impl<T> BinSerializeArgs for &T
+where
+    T: BinSerializeArgs,
+{
+    type Args = T::Args;
+    fn encode_with(&self, buf: &mut dyn BinWrite, attrs: Attrs, args: Self::Args) -> Result<()> {
+        (*self).encode_with(buf, attrs, args)
+    }
+}
 
 impl BinSerialize for &str {
     fn encode_to(&self, buf: &mut dyn BinWrite, _attrs: Attrs) -> Result<()> {
@@ -240,14 +254,47 @@ impl BinDeserialize for String {
     }
 }
 
+impl<'de: 'a, 'a> BinDeserializeBorrowed<'de> for &'a str {
+    fn decode_borrowed(buf: &mut dyn BorrowRead<'de>, _attrs: Attrs) -> Result<Self> {
+        let remaining = buf.remaining();
+        let len = match remaining.iter().position(|&b| b == 0) {
+            Some(len) => len,
+            None => return Err(BinError::InsufficientData),
+        };
+        let data = buf.take(len + 1)?;
+        match std::str::from_utf8(&data[..len]) {
+            Ok(s) => Ok(s),
+            // Only the owned error converts into BinError, so the bytes are copied to report it
+            Err(_) => Err(String::from_utf8(data[..len].to_vec()).unwrap_err().into()),
+        }
+    }
+}
+
+// Byte slices are (de)serialized as Vec<u8> is, being borrowed from the input when decoded
+impl BinSerialize for &[u8] {
+    fn encode_to(&self, buf: &mut dyn BinWrite, attrs: Attrs) -> Result<()> {
+        attrs.encode_length(buf, self.len() as u64)?;
+        Ok(buf.write_all(self)?)
+    }
+}
+impl<'de: 'a, 'a> BinDeserializeBorrowed<'de> for &'a [u8] {
+    fn decode_borrowed(buf: &mut dyn BorrowRead<'de>, attrs: Attrs) -> Result<Self> {
+        match attrs.decode_length(buf)? {
+            Some(len) => buf.take(len.try_into()?),
+            None => Ok(buf.take_rest()),
+        }
+    }
+}
+
 impl<T> BinSerialize for Vec<T>
 where
     T: BinSerialize,
 {
     fn encode_to(&self, buf: &mut dyn BinWrite, attrs: Attrs) -> Result<()> {
         attrs.encode_length(buf, self.len() as u64)?;
+        let elem_attrs = attrs.for_element();
         for elem in self {
-            elem.encode_to(buf, attrs)?;
+            elem.encode_to(buf, elem_attrs)?;
         }
         Ok(())
     }
@@ -258,16 +305,17 @@ where
 {
     fn decode_from(buf: &mut dyn BinRead, attrs: Attrs) -> Result<Self> {
         let len = attrs.decode_length(buf)?;
+        let elem_attrs = attrs.for_element();
         let mut v = vec![];
         if let Some(len) = len {
             let len: usize = len.try_into()?; // usize might be u32, so we need to check
             for _ in 0..len {
-                v.push(T::decode_from(buf, attrs)?);
+                v.push(T::decode_from(buf, elem_attrs)?);
             }
         } else {
             #[allow(clippy::while_let_loop)]
             loop {
-                match T::decode_from(buf, attrs) {
+                match T::decode_from(buf, elem_attrs) {
                     Ok(elem) => v.push(elem),
                     Err(BinError::InsufficientData) => break,
                     Err(e) => return Err(e),
@@ -283,9 +331,10 @@ where
     T: BinDeserialize,
 {
     fn decode_from(buf: &mut dyn BinRead, attrs: Attrs) -> Result<Self> {
+        let elem_attrs = attrs.for_element();
         let mut v = Vec::with_capacity(N);
         for _ in 0..N {
-            v.push(T::decode_from(buf, attrs)?);
+            v.push(T::decode_from(buf, elem_attrs)?);
         }
         Ok(v.try_into().ok().unwrap())
     }
@@ -296,13 +345,72 @@ where
     T: BinSerialize,
 {
     fn encode_to(&self, buf: &mut dyn BinWrite, attrs: Attrs) -> Result<()> {
+        let elem_attrs = attrs.for_element();
         for item in self {
-            item.encode_to(buf, attrs)?;
+            item.encode_to(buf, elem_attrs)?;
         }
         Ok(())
     }
 }
 
+// Maps are (de)serialized as their length, followed by each key and its value
+macro_rules! map_impls {
+    ($map:ident, $($bound:path),*) => {
+        impl<K, V> BinSerialize for $map<K, V>
+        where
+            K: BinSerialize,
+            V: BinSerialize,
+        {
+            fn encode_to(&self, buf: &mut dyn BinWrite, attrs: Attrs) -> Result<()> {
+                attrs.encode_length(buf, self.len() as u64)?;
+                let (key_attrs, value_attrs) = (attrs.for_key(), attrs.for_value());
+                for (k, v) in self {
+                    k.encode_to(buf, key_attrs)?;
+                    v.encode_to(buf, value_attrs)?;
+                }
+                Ok(())
+            }
+        }
+        impl<K, V> BinDeserialize for $map<K, V>
+        where
+            K: BinDeserialize $(+ $bound)*,
+            V: BinDeserialize,
+        {
+            fn decode_from(buf: &mut dyn BinRead, attrs: Attrs) -> Result<Self> {
+                let len = attrs.decode_length(buf)?;
+                let (key_attrs, value_attrs) = (attrs.for_key(), attrs.for_value());
+                let mut map = $map::new();
+                if let Some(len) = len {
+                    let len: usize = len.try_into()?;
+                    for _ in 0..len {
+                        let k = K::decode_from(buf, key_attrs)?;
+                        map.insert(k, V::decode_from(buf, value_attrs)?);
+                    }
+                } else {
+                    // As with Vec, decoding stops at the end of the input, dropping an entry that
+                    // is cut short there
+                    loop {
+                        let k = match K::decode_from(buf, key_attrs) {
+                            Ok(k) => k,
+                            Err(BinError::InsufficientData) => break,
+                            Err(e) => return Err(e),
+                        };
+                        match V::decode_from(buf, value_attrs) {
+                            Ok(v) => map.insert(k, v),
+                            Err(BinError::InsufficientData) => break,
+                            Err(e) => return Err(e),
+                        };
+                    }
+                }
+                Ok(map)
+            }
+        }
+    };
+}
+
+map_impls!(HashMap, Eq, Hash);
+map_impls!(BTreeMap, Ord);
+
 impl<T> BinDeserialize for Box<T>
 where
     T: BinDeserialize,
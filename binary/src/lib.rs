@@ -1,8 +1,14 @@
 mod de;
-pub use de::{decode_from_bytes, decode_from_stream, BinDeserialize};
+pub use de::{
+    decode_from_bytes, decode_from_bytes_with, decode_from_stream, decode_from_stream_with,
+    BinDeserialize, BinDeserializeArgs, BinDeserializeBorrowed, BinDeserializeTagged,
+};
 
 mod ser;
-pub use ser::{encode_to_bytes, encode_to_stream, BinSerialize};
+pub use ser::{
+    encode_to_bytes, encode_to_bytes_with, encode_to_stream, encode_to_stream_with, BinSerialize,
+    BinSerializeArgs, BinSerializeTagged,
+};
 
 pub mod attr;
 
@@ -11,17 +17,64 @@ pub use error::{BinError, Result};
 
 mod impls;
 
+pub mod session;
+pub use session::Session;
+
 mod stream_rw;
-pub use stream_rw::{BinRead, BinWrite};
+pub use stream_rw::{
+    stream_padding_to, stream_position, BinRead, BinWrite, BorrowRead, Counted, StreamLevel,
+    Tracked,
+};
+
+mod size;
+pub use size::{BinSize, SizeBounds};
 
-pub use binary_derive::{BinDeserialize, BinSerialize};
+mod view;
+pub use view::BinViewField;
 
+pub use binary_derive::{BinDeserialize, BinSerialize, BinSize, BinView};
+
+// BinFlags is implemented by types usable as #[binary(flags)] words. Bits are exchanged as u64s;
+// bits beyond the width of the type are ignored. Implementations provide bits and set_bits, which
+// the other methods are written in terms of.
 pub trait BinFlags {
     const ZERO: Self;
-    fn has(&self, v: u64) -> bool;
-    fn set(&mut self, v: u64);
+    fn bits(&self) -> u64;
+    // Replaces the bits selected by `mask` with the corresponding bits of `value`
+    fn set_bits(&mut self, mask: u64, value: u64);
+
+    fn has(&self, v: u64) -> bool {
+        self.bits() & v != 0
+    }
+    fn matches(&self, mask: u64, eq: u64) -> bool {
+        self.bits() & mask == eq
+    }
+    fn set(&mut self, v: u64) {
+        self.set_bits(v, v)
+    }
+    fn clear(&mut self, v: u64) {
+        self.set_bits(v, 0)
+    }
 }
 
+/// RawBytes is implemented by the types that may be members of #[binary(raw)] unions, which are
+/// written and read as the bytes of their memory.
+///
+/// # Safety
+///
+/// Every byte of an implementor must be initialized, with no padding, and any bytes must make a
+/// valid value.
+pub unsafe trait RawBytes: Copy {}
+
+macro_rules! raw_bytes {
+    ($($ty:ty),*) => {
+        $(unsafe impl RawBytes for $ty {})*
+    };
+}
+
+raw_bytes!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64);
+unsafe impl<T: RawBytes, const N: usize> RawBytes for [T; N] {}
+
 // DeOption helper, for binary_derive to un-Option-ify types for decoding when using flags
 pub trait DeOption: private::Sealed {
     type Assoc;
@@ -1,16 +1,35 @@
 use crate::attr::Attrs;
-use crate::{BinWrite, Result};
+use crate::{BinWrite, Result, StreamLevel, Tracked};
 
 pub trait BinSerialize {
     fn encode_to(&self, buf: &mut dyn BinWrite, attrs: Attrs) -> Result<()>;
 }
 
+// BinSerializeArgs is the encoding counterpart of BinDeserializeArgs
+pub trait BinSerializeArgs {
+    type Args;
+    fn encode_with(&self, buf: &mut dyn BinWrite, attrs: Attrs, args: Self::Args) -> Result<()>;
+}
+
+// BinSerializeTagged is implemented by derived enums, to encode their body apart from their tag
+// where the tag is written elsewhere, such as in a header; see #[binary(tag = "...")]
+pub trait BinSerializeTagged {
+    type Tag;
+    // The tag the value is encoded with
+    fn tag(&self) -> Self::Tag;
+    // Fails with TagMismatch if `tag` does not select the value's variant
+    fn encode_untagged(&self, buf: &mut dyn BinWrite, attrs: Attrs, tag: &Self::Tag) -> Result<()>;
+}
+
 pub fn encode_to_bytes<T>(t: T) -> Result<Vec<u8>>
 where
     T: BinSerialize,
 {
     let mut buf = vec![];
-    t.encode_to(&mut buf, Attrs::zero())?;
+    {
+        let _level = StreamLevel::enter(Some(0));
+        t.encode_to(&mut Tracked::new(&mut buf), Attrs::zero())?;
+    }
     Ok(buf)
 }
 
@@ -18,5 +37,26 @@ pub fn encode_to_stream<T>(t: T, s: &mut dyn BinWrite) -> Result<()>
 where
     T: BinSerialize,
 {
-    t.encode_to(s, Attrs::zero())
+    let _level = StreamLevel::enter(Some(0));
+    t.encode_to(&mut Tracked::new(s), Attrs::zero())
+}
+
+pub fn encode_to_bytes_with<T>(t: T, args: T::Args) -> Result<Vec<u8>>
+where
+    T: BinSerializeArgs,
+{
+    let mut buf = vec![];
+    {
+        let _level = StreamLevel::enter(Some(0));
+        t.encode_with(&mut Tracked::new(&mut buf), Attrs::zero(), args)?;
+    }
+    Ok(buf)
+}
+
+pub fn encode_to_stream_with<T>(t: T, s: &mut dyn BinWrite, args: T::Args) -> Result<()>
+where
+    T: BinSerializeArgs,
+{
+    let _level = StreamLevel::enter(Some(0));
+    t.encode_with(&mut Tracked::new(s), Attrs::zero(), args)
 }
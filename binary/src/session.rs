@@ -0,0 +1,88 @@
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// Session holds state shared by every value (de)serialized while it is active, such as a string
+// table, a compression dictionary or a running status byte. Values are keyed by their type, so
+// each piece of state should have a type of its own.
+#[derive(Default)]
+pub struct Session {
+    values: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.values.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+
+    pub fn get_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.values.get_mut(&TypeId::of::<T>())?.downcast_mut()
+    }
+
+    pub fn get_or_default<T: Any + Default>(&mut self) -> &mut T {
+        self.values
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(T::default()))
+            .downcast_mut()
+            .unwrap()
+    }
+
+    pub fn insert<T: Any>(&mut self, v: T) -> Option<T> {
+        let old = self.values.insert(TypeId::of::<T>(), Box::new(v))?;
+        Some(*old.downcast().unwrap())
+    }
+
+    pub fn remove<T: Any>(&mut self) -> Option<T> {
+        let old = self.values.remove(&TypeId::of::<T>())?;
+        Some(*old.downcast().unwrap())
+    }
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Vec<Session>> = const { RefCell::new(Vec::new()) };
+}
+
+// Makes `session` the active session while `f` runs, so that any encode_to or decode_from
+// called within it can reach the session through `with`. Sessions nest; the innermost is active.
+// However `f` ends, by returning an error, panicking or otherwise, the session is handed back
+// with whatever was stored in it and the session active before is restored.
+pub fn enter<R>(session: &mut Session, f: impl FnOnce() -> R) -> R {
+    // Moves the session back out of the stack when dropped
+    struct Restore<'a>(&'a mut Session);
+    impl Drop for Restore<'_> {
+        fn drop(&mut self) {
+            if let Some(s) = ACTIVE.with(|a| a.borrow_mut().pop()) {
+                *self.0 = s;
+            }
+        }
+    }
+
+    ACTIVE.with(|a| a.borrow_mut().push(std::mem::take(session)));
+    let _restore = Restore(session);
+    f()
+}
+
+// Calls `f` with the active session, returning None if there is none. The session is detached
+// while `f` runs, so values (de)serialized from within `f` do not see it.
+pub fn with<R>(f: impl FnOnce(&mut Session) -> R) -> Option<R> {
+    struct Reattach(Session);
+    impl Drop for Reattach {
+        fn drop(&mut self) {
+            let s = std::mem::take(&mut self.0);
+            ACTIVE.with(|a| a.borrow_mut().push(s));
+        }
+    }
+
+    let mut session = Reattach(ACTIVE.with(|a| a.borrow_mut().pop())?);
+    Some(f(&mut session.0))
+}
+
+// A copy of the active session's T, if there is one; for reading the session from expressions in
+// #[binary(...)] attributes, as in `if = session::get::<Version>().map_or(false, |v| v.0 >= 2)`
+pub fn get<T: Any + Clone>() -> Option<T> {
+    with(|s| s.get::<T>().cloned()).flatten()
+}
@@ -0,0 +1,190 @@
+use std::collections::{BTreeMap, HashMap};
+
+// BinSize gives bounds on how many bytes a type encodes to, which hold whatever attributes it is
+// (de)serialized with. MAX_SIZE is None for types of unbounded size, and FIXED_SIZE is Some for
+// types that always encode to the same number of bytes.
+pub trait BinSize {
+    const MIN_SIZE: usize;
+    const MAX_SIZE: Option<usize>;
+    const FIXED_SIZE: Option<usize> = match Self::MAX_SIZE {
+        Some(max) if max == Self::MIN_SIZE => Some(max),
+        _ => None,
+    };
+    // Whether the type is preceded by its length when given len(...), which MIN_SIZE leaves out
+    const LEN_PREFIXED: bool = false;
+}
+
+// SizeBounds helper, for binary_derive to compose the sizes of fields in constant expressions
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SizeBounds {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl SizeBounds {
+    pub const ANY: SizeBounds = SizeBounds { min: 0, max: None };
+
+    pub const fn exact(n: usize) -> Self {
+        SizeBounds {
+            min: n,
+            max: Some(n),
+        }
+    }
+
+    // The bounds of T, preceded by a length of `len` bytes if T has one
+    pub const fn of<T: BinSize>(len: usize) -> Self {
+        let len = if T::LEN_PREFIXED { len } else { 0 };
+        SizeBounds::exact(len).then(SizeBounds {
+            min: T::MIN_SIZE,
+            max: T::MAX_SIZE,
+        })
+    }
+
+    // These bounds, followed by `next`
+    pub const fn then(self, next: SizeBounds) -> Self {
+        SizeBounds {
+            min: self.min + next.min,
+            max: match (self.max, next.max) {
+                (Some(a), Some(b)) => a.checked_add(b),
+                _ => None,
+            },
+        }
+    }
+
+    // Bounds covering either these or `other`
+    pub const fn or(self, other: SizeBounds) -> Self {
+        SizeBounds {
+            min: if self.min < other.min {
+                self.min
+            } else {
+                other.min
+            },
+            max: match (self.max, other.max) {
+                (Some(a), Some(b)) if a > b => Some(a),
+                (Some(_), Some(b)) => Some(b),
+                _ => None,
+            },
+        }
+    }
+
+    // These bounds, less a `part` of the value that is left out, such as the tag of an enum
+    // encoded without it
+    pub const fn without(self, part: SizeBounds) -> Self {
+        SizeBounds {
+            min: match part.max {
+                Some(max) => self.min.saturating_sub(max),
+                None => 0,
+            },
+            max: match self.max {
+                Some(max) => Some(max.saturating_sub(part.min)),
+                None => None,
+            },
+        }
+    }
+
+    // These bounds, for a value that may be left out
+    pub const fn optional(self) -> Self {
+        self.or(SizeBounds::exact(0))
+    }
+
+    // These bounds, padded to the next multiple of `align`
+    pub const fn align(self, align: usize) -> Self {
+        SizeBounds {
+            min: self.min + (align - self.min % align) % align,
+            max: match self.max {
+                Some(max) => max.checked_add((align - max % align) % align),
+                None => None,
+            },
+        }
+    }
+
+    // These bounds, padded to the next multiple of `align` counted from a position they do not
+    // know, such as the start of the stream
+    pub const fn align_stream(self, align: usize) -> Self {
+        SizeBounds {
+            min: self.min,
+            max: match self.max {
+                Some(max) => max.checked_add(align - 1),
+                None => None,
+            },
+        }
+    }
+}
+
+macro_rules! size_num {
+    ($ty:ty) => {
+        impl BinSize for $ty {
+            const MIN_SIZE: usize = std::mem::size_of::<$ty>();
+            const MAX_SIZE: Option<usize> = Some(std::mem::size_of::<$ty>());
+        }
+    };
+}
+
+size_num!(bool);
+size_num!(u8);
+size_num!(i8);
+size_num!(u16);
+size_num!(i16);
+size_num!(u32);
+size_num!(i32);
+size_num!(u64);
+size_num!(i64);
+size_num!(f32);
+size_num!(f64);
+size_num!(());
+
+impl<T, const N: usize> BinSize for [T; N]
+where
+    T: BinSize,
+{
+    const MIN_SIZE: usize = T::MIN_SIZE * N;
+    const MAX_SIZE: Option<usize> = match T::MAX_SIZE {
+        Some(max) => max.checked_mul(N),
+        None if N == 0 => Some(0),
+        None => None,
+    };
+}
+
+// Strings are NUL-terminated
+impl BinSize for String {
+    const MIN_SIZE: usize = 1;
+    const MAX_SIZE: Option<usize> = None;
+}
+impl BinSize for &str {
+    const MIN_SIZE: usize = 1;
+    const MAX_SIZE: Option<usize> = None;
+}
+
+// Containers hold as many elements as their length, or the rest of the input without one
+macro_rules! size_container {
+    ([$($param:ident),*] $ty:ty) => {
+        impl<$($param),*> BinSize for $ty {
+            const MIN_SIZE: usize = 0;
+            const MAX_SIZE: Option<usize> = None;
+            const LEN_PREFIXED: bool = true;
+        }
+    };
+}
+
+size_container!([] & [u8]);
+size_container!([T] Vec<T>);
+size_container!([K, V] HashMap<K, V>);
+size_container!([K, V] BTreeMap<K, V>);
+
+impl<T> BinSize for Box<T>
+where
+    T: BinSize,
+{
+    const MIN_SIZE: usize = T::MIN_SIZE;
+    const MAX_SIZE: Option<usize> = T::MAX_SIZE;
+    const LEN_PREFIXED: bool = T::LEN_PREFIXED;
+}
+
+impl<T> BinSize for &T
+where
+    T: BinSize,
+{
+    const MIN_SIZE: usize = T::MIN_SIZE;
+    const MAX_SIZE: Option<usize> = T::MAX_SIZE;
+    const LEN_PREFIXED: bool = T::LEN_PREFIXED;
+}
@@ -1,4 +1,5 @@
-use crate::Result;
+use crate::{BinError, Result};
+use std::cell::RefCell;
 
 macro_rules! get_stdnum_be {
     ($name:ident,$ty:ty) => {
@@ -38,9 +39,57 @@ pub trait BinRead: std::io::BufRead {
     get_stdnum_le!(get_u32_le, u32);
     get_stdnum_be!(get_u64_be, u64);
     get_stdnum_le!(get_u64_le, u64);
+
+    // Skips `len` bytes of padding, checking that each matches `fill` if one is given
+    fn skip_padding(&mut self, len: u64, fill: Option<u8>) -> Result<()> {
+        for _ in 0..len {
+            let v = self.get_u8()?;
+            match fill {
+                Some(fill) if v != fill => return Err(BinError::InvalidPadding(v)),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Reads everything left in the stream, such as the rest of a nested value's buffer
+    fn read_rest(&mut self) -> Result<Vec<u8>> {
+        let mut rest = vec![];
+        self.read_to_end(&mut rest)?;
+        Ok(rest)
+    }
 }
 impl<T: std::io::BufRead> BinRead for T {}
 
+// BorrowRead is implemented by readers over an in-memory slice, which values decoded through
+// BinDeserializeBorrowed may borrow from rather than copy out of
+pub trait BorrowRead<'de>: BinRead {
+    // The unread rest of the input; consume() advances through it
+    fn remaining(&self) -> &'de [u8];
+
+    // Takes the next `len` bytes of the input
+    fn take(&mut self, len: usize) -> Result<&'de [u8]> {
+        let remaining = self.remaining();
+        if remaining.len() < len {
+            return Err(BinError::InsufficientData);
+        }
+        self.consume(len);
+        Ok(&remaining[..len])
+    }
+
+    // Takes everything left in the input
+    fn take_rest(&mut self) -> &'de [u8] {
+        let remaining = self.remaining();
+        self.consume(remaining.len());
+        remaining
+    }
+}
+impl<'de> BorrowRead<'de> for &'de [u8] {
+    fn remaining(&self) -> &'de [u8] {
+        self
+    }
+}
+
 macro_rules! put_stdnum_be {
     ($name:ident,$ty:ty) => {
         fn $name(&mut self, v: $ty) -> Result<()> {
@@ -77,5 +126,175 @@ pub trait BinWrite: std::io::Write {
     put_stdnum_le!(put_u32_le, u32);
     put_stdnum_be!(put_u64_be, u64);
     put_stdnum_le!(put_u64_le, u64);
+
+    fn put_padding(&mut self, len: u64, fill: u8) -> Result<()> {
+        for _ in 0..len {
+            self.put_u8(fill)?;
+        }
+        Ok(())
+    }
 }
 impl<T: std::io::Write> BinWrite for T {}
+
+// Counted wraps a reader or writer, tracking how many bytes have passed through it so that
+// fields can be aligned relative to the start of their enclosing structure. A struct is aligned
+// within its container by aligning the field holding it; alignment relative to the start of the
+// stream, with #[binary(align(stream = N))], uses stream_position instead.
+pub struct Counted<'a, T: ?Sized> {
+    inner: &'a mut T,
+    position: u64,
+}
+
+impl<'a, T: ?Sized> Counted<'a, T> {
+    pub fn new(inner: &'a mut T) -> Self {
+        Counted { inner, position: 0 }
+    }
+
+    // As new, for a structure that began `position` bytes before `inner`'s next byte, such as the
+    // variant of an enum whose tag has been read or written
+    pub fn starting_at(inner: &'a mut T, position: u64) -> Self {
+        Counted { inner, position }
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    // The number of padding bytes needed to advance to the next multiple of `align`
+    pub fn padding_to(&self, align: u64) -> u64 {
+        (align - self.position % align) % align
+    }
+}
+
+impl<T: std::io::Read + ?Sized> std::io::Read for Counted<'_, T> {
+    fn read(&mut self, data: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(data)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl<T: std::io::BufRead + ?Sized> std::io::BufRead for Counted<'_, T> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+    fn consume(&mut self, amt: usize) {
+        self.position += amt as u64;
+        self.inner.consume(amt)
+    }
+}
+
+impl<'de, T: BorrowRead<'de> + ?Sized> BorrowRead<'de> for Counted<'_, T> {
+    fn remaining(&self) -> &'de [u8] {
+        self.inner.remaining()
+    }
+}
+
+impl<T: std::io::Write + ?Sized> std::io::Write for Counted<'_, T> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(data)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+thread_local! {
+    // The position in the stream of the next byte read or written, for each level of tracking
+    // entered, the innermost last; None where a nested value's buffer has no known position
+    static LEVELS: RefCell<Vec<Option<u64>>> = const { RefCell::new(Vec::new()) };
+}
+
+// The position of the next byte to be read or written, counted from the start of the stream given
+// to the top-level function, such as decode_from_bytes or encode_to_stream, that is running. None
+// outside of those functions. Bytes read or written through buffers other than the one passed to
+// encode_to or decode_from are not counted, except for the buffers of nested values.
+pub fn stream_position() -> Option<u64> {
+    LEVELS.with(|l| l.borrow().last().copied().flatten())
+}
+
+// The number of padding bytes needed to advance to the next multiple of `align` in the stream
+pub fn stream_padding_to(align: u64) -> Result<u64> {
+    match stream_position() {
+        Some(position) => Ok((align - position % align) % align),
+        None => Err(BinError::UnknownStreamPosition),
+    }
+}
+
+// StreamLevel enters a level of stream position tracking, starting at `position`, for a stream or
+// the buffer of a nested value. The level before is restored when it is dropped.
+pub struct StreamLevel(());
+
+impl StreamLevel {
+    pub fn enter(position: Option<u64>) -> Self {
+        LEVELS.with(|l| l.borrow_mut().push(position));
+        StreamLevel(())
+    }
+}
+
+impl Drop for StreamLevel {
+    fn drop(&mut self) {
+        LEVELS.with(|l| l.borrow_mut().pop());
+    }
+}
+
+// Tracked wraps a reader or writer, advancing the position of the tracking level that was
+// innermost when it was made as bytes pass through it
+pub struct Tracked<'a, T: ?Sized> {
+    inner: &'a mut T,
+    level: Option<usize>,
+}
+
+impl<'a, T: ?Sized> Tracked<'a, T> {
+    pub fn new(inner: &'a mut T) -> Self {
+        let level = LEVELS.with(|l| l.borrow().len().checked_sub(1));
+        Tracked { inner, level }
+    }
+
+    fn advance(&self, n: usize) {
+        if let Some(level) = self.level {
+            LEVELS.with(|l| {
+                if let Some(Some(position)) = l.borrow_mut().get_mut(level) {
+                    *position += n as u64;
+                }
+            });
+        }
+    }
+}
+
+impl<T: std::io::Read + ?Sized> std::io::Read for Tracked<'_, T> {
+    fn read(&mut self, data: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(data)?;
+        self.advance(n);
+        Ok(n)
+    }
+}
+
+impl<T: std::io::BufRead + ?Sized> std::io::BufRead for Tracked<'_, T> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+    fn consume(&mut self, amt: usize) {
+        self.advance(amt);
+        self.inner.consume(amt)
+    }
+}
+
+impl<'de, T: BorrowRead<'de> + ?Sized> BorrowRead<'de> for Tracked<'_, T> {
+    fn remaining(&self) -> &'de [u8] {
+        self.inner.remaining()
+    }
+}
+
+impl<T: std::io::Write + ?Sized> std::io::Write for Tracked<'_, T> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(data)?;
+        self.advance(n);
+        Ok(n)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
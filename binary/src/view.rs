@@ -0,0 +1,72 @@
+use crate::attr::{Attrs, Endian};
+use std::convert::TryInto;
+
+// BinViewField is implemented by fixed-size types that views generated by #[derive(BinView)]
+// read and write in place, at offsets computed from their sizes. Structs deriving BinView
+// implement it too, so that they can be fields of other views.
+pub trait BinViewField: Sized {
+    const SIZE: usize;
+    // `bytes` holds exactly SIZE bytes
+    fn read_view(bytes: &[u8], attrs: Attrs) -> Self;
+    fn write_view(&self, bytes: &mut [u8], attrs: Attrs);
+}
+
+macro_rules! view_num {
+    ($ty:ty) => {
+        impl BinViewField for $ty {
+            const SIZE: usize = std::mem::size_of::<$ty>();
+            fn read_view(bytes: &[u8], attrs: Attrs) -> Self {
+                let data = bytes.try_into().unwrap();
+                match attrs.endian {
+                    Endian::Big => <$ty>::from_be_bytes(data),
+                    Endian::Little => <$ty>::from_le_bytes(data),
+                }
+            }
+            fn write_view(&self, bytes: &mut [u8], attrs: Attrs) {
+                let data = match attrs.endian {
+                    Endian::Big => self.to_be_bytes(),
+                    Endian::Little => self.to_le_bytes(),
+                };
+                bytes.copy_from_slice(&data);
+            }
+        }
+    };
+}
+
+view_num!(u8);
+view_num!(i8);
+view_num!(u16);
+view_num!(i16);
+view_num!(u32);
+view_num!(i32);
+view_num!(u64);
+view_num!(i64);
+view_num!(f32);
+view_num!(f64);
+
+impl BinViewField for bool {
+    const SIZE: usize = 1;
+    fn read_view(bytes: &[u8], _attrs: Attrs) -> Self {
+        bytes[0] != 0
+    }
+    fn write_view(&self, bytes: &mut [u8], _attrs: Attrs) {
+        bytes[0] = u8::from(*self);
+    }
+}
+
+impl<T, const N: usize> BinViewField for [T; N]
+where
+    T: BinViewField,
+{
+    const SIZE: usize = T::SIZE * N;
+    fn read_view(bytes: &[u8], attrs: Attrs) -> Self {
+        let elem_attrs = attrs.for_element();
+        std::array::from_fn(|i| T::read_view(&bytes[i * T::SIZE..(i + 1) * T::SIZE], elem_attrs))
+    }
+    fn write_view(&self, bytes: &mut [u8], attrs: Attrs) {
+        let elem_attrs = attrs.for_element();
+        for (i, elem) in self.iter().enumerate() {
+            elem.write_view(&mut bytes[i * T::SIZE..(i + 1) * T::SIZE], elem_attrs);
+        }
+    }
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::useless_vec)] // roundtrip! compares against the Vec returned by encode_to_bytes
+
 use binary::{BinDeserialize, BinSerialize};
 use std::convert::TryFrom;
 
@@ -8,6 +10,13 @@ macro_rules! roundtrip {
     };
 }
 
+macro_rules! roundtrip_with {
+    ($val:expr, $args:expr, $bytes:expr) => {
+        assert_eq!(binary::encode_to_bytes_with($val, $args), Ok($bytes));
+        assert_eq!(binary::decode_from_bytes_with(&$bytes, $args), Ok($val));
+    };
+}
+
 #[test]
 fn test_primitive() {
     roundtrip!(42u8, vec![42]);
@@ -133,6 +142,334 @@ fn test_enum_nested() {
         Nest::C { x: 42, y: 100 },
         vec![2, 8, 42, 0, 0, 0, 100, 0, 0, 0]
     );
+    assert_eq!(
+        binary::decode_from_bytes::<Nest>(&[1, 7, 42, 116, 101, 115, 116, 0, 9]),
+        Err(binary::BinError::TrailingData(1))
+    );
+}
+
+#[test]
+fn test_nest_fields() {
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[binary(nest(u8))]
+    struct Inner {
+        a: u8,
+        b: u16,
+    }
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Outer {
+        #[binary(nest(u16, big))]
+        name: String,
+        inner: Inner,
+        #[binary(nest(u8))]
+        values: Vec<u8>,
+    }
+
+    roundtrip!(
+        Outer {
+            name: "ab".to_string(),
+            inner: Inner { a: 1, b: 2 },
+            values: vec![5, 6],
+        },
+        vec![0, 3, b'a', b'b', 0, 3, 1, 2, 0, 2, 5, 6]
+    );
+    assert_eq!(
+        binary::decode_from_bytes::<Outer>(&[0, 4, b'a', b'b', 0, 0, 3, 1, 2, 0, 0]),
+        Err(binary::BinError::TrailingData(1))
+    );
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[repr(u8)]
+    #[binary(nest(u8))]
+    enum Mixed {
+        A(u8),
+        #[binary(nest(none))]
+        B(u8),
+        #[binary(nest(u16, big))]
+        C(u8),
+    }
+
+    roundtrip!(Mixed::A(7), vec![0, 1, 7]);
+    roundtrip!(Mixed::B(7), vec![1, 7]);
+    roundtrip!(Mixed::C(7), vec![2, 0, 1, 7]);
+}
+
+#[test]
+fn test_inner_attrs() {
+    use std::collections::{BTreeMap, HashMap};
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Table {
+        #[binary(len(u16), inner(len(u8), big))]
+        rows: Vec<Vec<u16>>,
+        #[binary(len(u8), key(big), value(len(u16), inner(len(u8))))]
+        index: BTreeMap<u16, Vec<Vec<u8>>>,
+        #[binary(len(u8))]
+        names: HashMap<u8, String>,
+    }
+
+    roundtrip!(
+        Table {
+            rows: vec![vec![1, 2], vec![]],
+            index: vec![(0x0102, vec![vec![7]])].into_iter().collect(),
+            names: vec![(5, "a".to_string())].into_iter().collect(),
+        },
+        vec![
+            2, 0, 2, 0, 1, 0, 2, 0, // rows
+            1, 1, 2, 1, 0, 1, 7, // index
+            1, 5, b'a', 0, // names
+        ]
+    );
+
+    // Without a length, a map holds the rest of the input, as a Vec does
+    let map: BTreeMap<u8, u16> = vec![(1, 0x0201), (2, 0x0403)].into_iter().collect();
+    roundtrip!(map.clone(), vec![1, 1, 2, 2, 3, 4]);
+    assert_eq!(
+        binary::decode_from_bytes(&[1, 1, 2, 2, 3, 4, 5]),
+        Ok(map.clone())
+    );
+    assert_eq!(
+        binary::decode_from_bytes(&[1, 1, 2, 2, 3, 4, 5, 6]),
+        Ok(map)
+    );
+    assert_eq!(
+        binary::decode_from_bytes::<Vec<u16>>(&[1, 2, 3]),
+        Ok(vec![0x0201])
+    );
+}
+
+#[test]
+fn test_bound() {
+    // Inferring `Vec<Tree<T>>: BinSerialize` would require Tree<T> to implement it already
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Tree<T> {
+        value: T,
+        #[binary(
+            len(u8),
+            bound(serialize = "T: BinSerialize", deserialize = "T: BinDeserialize")
+        )]
+        children: Vec<Tree<T>>,
+    }
+
+    roundtrip!(
+        Tree {
+            value: 1u8,
+            children: vec![Tree {
+                value: 2,
+                children: vec![]
+            }],
+        },
+        vec![1, 1, 2, 0]
+    );
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[binary(bound = "")]
+    struct Count<T> {
+        count: u8,
+        #[binary(with = "skip")]
+        marker: std::marker::PhantomData<T>,
+    }
+
+    mod skip {
+        pub fn encode<T>(
+            _: &T,
+            _: &mut dyn binary::BinWrite,
+            _: binary::attr::Attrs,
+        ) -> binary::Result<()> {
+            Ok(())
+        }
+
+        pub fn decode<T: Default>(
+            _: &mut dyn binary::BinRead,
+            _: binary::attr::Attrs,
+        ) -> binary::Result<T> {
+            Ok(T::default())
+        }
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct NotBinary;
+    roundtrip!(
+        Count::<NotBinary> {
+            count: 3,
+            marker: std::marker::PhantomData,
+        },
+        vec![3]
+    );
+}
+
+mod facade {
+    pub use binary as wire;
+}
+
+// Generated code must not depend on `binary` being in scope, nor on the names `vec` and `Vec`
+mod renamed {
+    #[allow(unused_macros)]
+    macro_rules! vec {
+        ($($t:tt)*) => {
+            compile_error!("vec! used by generated code")
+        };
+    }
+    #[allow(dead_code)]
+    struct Vec;
+
+    use crate::facade::wire::{BinDeserialize, BinSerialize};
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[binary(crate = "crate::facade::wire")]
+    pub struct Wrapped {
+        #[binary(nest(u8))]
+        pub inner: u16,
+        #[binary(flags)]
+        pub flags: u8,
+        #[binary(flags(0x01))]
+        pub extra: Option<u8>,
+    }
+}
+
+#[test]
+fn test_crate_path() {
+    roundtrip!(
+        renamed::Wrapped {
+            inner: 0x0102,
+            flags: 1,
+            extra: Some(3),
+        },
+        vec![2, 2, 1, 1, 3]
+    );
+}
+
+#[test]
+fn test_rest() {
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[binary(nest(u8))]
+    struct Message {
+        id: u8,
+        #[binary(rest)]
+        unknown: Vec<u8>,
+    }
+
+    // Fields added by a newer peer are kept, without consuming the next message
+    let bytes = vec![3, 1, 0xaa, 0xbb, 1, 2];
+    let decoded: Vec<Message> = binary::decode_from_bytes(&bytes).unwrap();
+    assert_eq!(
+        decoded,
+        vec![
+            Message {
+                id: 1,
+                unknown: vec![0xaa, 0xbb]
+            },
+            Message {
+                id: 2,
+                unknown: vec![]
+            },
+        ]
+    );
+    assert_eq!(binary::encode_to_bytes(decoded), Ok(bytes));
+}
+
+#[test]
+fn test_borrowed() {
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Name<'a> {
+        #[binary(len(u8))]
+        bytes: &'a [u8],
+        text: &'a str,
+    }
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[binary(tag(u8))]
+    enum Record<'a, T> {
+        #[binary(tag = 1)]
+        Name(#[binary(nest(u8))] Name<'a>),
+        #[binary(tag = 2)]
+        Value(T),
+    }
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Packet<'a> {
+        id: u8,
+        #[binary(align = 2)]
+        record: Record<'a, u16>,
+        #[binary(rest)]
+        rest: &'a [u8],
+    }
+
+    let bytes = vec![7, 0, 1, 6, 2, b'h', b'i', b'o', b'k', 0, 0xff];
+    let packet = Packet {
+        id: 7,
+        record: Record::Name(Name {
+            bytes: b"hi",
+            text: "ok",
+        }),
+        rest: &[0xff],
+    };
+    assert_eq!(binary::encode_to_bytes(&packet), Ok(bytes.clone()));
+    let decoded: Packet = binary::decode_from_bytes(&bytes).unwrap();
+    assert_eq!(decoded, packet);
+
+    // The decoded slices point into the input rather than copies of it
+    let input = bytes.as_ptr_range();
+    match decoded.record {
+        Record::Name(name) => {
+            assert!(input.contains(&name.bytes.as_ptr()));
+            assert!(input.contains(&name.text.as_ptr()));
+        }
+        _ => unreachable!(),
+    }
+    assert!(input.contains(&decoded.rest.as_ptr()));
+
+    roundtrip!(Record::<u16>::Value(0x102), vec![2, 2, 1]);
+    assert_eq!(
+        binary::decode_from_bytes::<Name>(&[1, b'x', b'y']),
+        Err(binary::BinError::InsufficientData)
+    );
+}
+
+#[test]
+fn test_flags_custom_word() {
+    // A flags word of its own type, relying on BinFlags for everything but bits and set_bits
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq, Clone)]
+    struct Word(u8);
+
+    impl binary::BinFlags for Word {
+        const ZERO: Self = Word(0);
+        fn bits(&self) -> u64 {
+            u64::from(self.0)
+        }
+        fn set_bits(&mut self, mask: u64, value: u64) {
+            self.0 = (self.0 & !(mask as u8)) | (value & mask) as u8
+        }
+    }
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Flags {
+        #[binary(flags)]
+        flags: Word,
+        #[binary(flags(0x01))]
+        a: Option<u8>,
+        #[binary(flags(mask = 0x06, eq = 0x04))]
+        b: Option<u8>,
+    }
+
+    roundtrip!(
+        Flags {
+            flags: Word(0x81),
+            a: Some(1),
+            b: None,
+        },
+        vec![0x81, 1]
+    );
+    // Encoding clears the bits of absent fields and sets those of present ones, keeping the rest
+    assert_eq!(
+        binary::encode_to_bytes(Flags {
+            flags: Word(0x83),
+            a: None,
+            b: Some(2),
+        }),
+        Ok(vec![0x84, 2])
+    );
 }
 
 #[test]
@@ -196,6 +533,321 @@ fn test_flags() {
     );
 }
 
+#[test]
+fn test_flag_words() {
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Header {
+        #[binary(flags)]
+        flags: u8,
+        #[binary(flags(0x01))]
+        a: Option<u8>,
+        #[binary(flags(mask = 0x30, eq = 0x10))]
+        short: Option<u16>,
+        #[binary(flags(mask = 0x30, eq = 0x20))]
+        long: Option<u32>,
+        #[binary(flags(strict))]
+        ext: u8,
+        #[binary(flags(0x01))]
+        b: Option<u8>,
+        #[binary(flags(0x80, word = "flags"))]
+        c: Option<u8>,
+    }
+
+    // Bits 0x40 of flags are not claimed by any field, and survive the round trip
+    roundtrip!(
+        Header {
+            flags: 0x51,
+            a: Some(1),
+            short: Some(2),
+            long: None,
+            ext: 0,
+            b: None,
+            c: None,
+        },
+        vec![0x51, 1, 2, 0, 0]
+    );
+    roundtrip!(
+        Header {
+            flags: 0xa0,
+            a: None,
+            short: None,
+            long: Some(3),
+            ext: 1,
+            b: Some(4),
+            c: Some(5),
+        },
+        vec![0xa0, 3, 0, 0, 0, 1, 4, 5]
+    );
+    assert_eq!(
+        binary::encode_to_bytes(Header {
+            flags: 0x11, // a and short are absent, so their bits are cleared
+            a: None,
+            short: None,
+            long: None,
+            ext: 1, // b is absent, so its bit is cleared
+            b: None,
+            c: Some(5),
+        }),
+        Ok(vec![0x80, 0, 5])
+    );
+    assert_eq!(
+        binary::decode_from_bytes::<Header>(&[0x00, 0x02]),
+        Err(binary::BinError::UnknownFlags(0x02))
+    );
+}
+
+#[test]
+fn test_flag_positions() {
+    // Unrelated fields between the flags word and the fields that depend on it
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Member {
+        magic: u16,
+        #[binary(flags)]
+        flags: u8,
+        mtime: u32,
+        #[binary(flags(0x04), align = 4)]
+        extra: Option<u16>,
+        #[binary(flags(0x08))]
+        name: Option<String>,
+    }
+
+    roundtrip!(
+        Member {
+            magic: 0x8b1f,
+            flags: 0x08,
+            mtime: 1,
+            extra: None,
+            name: Some("a".to_string()),
+        },
+        vec![0x1f, 0x8b, 0x08, 1, 0, 0, 0, 0, 0x61, 0]
+    );
+    roundtrip!(
+        Member {
+            magic: 0x8b1f,
+            flags: 0x04,
+            mtime: 1,
+            extra: Some(2),
+            name: None,
+        },
+        vec![0x1f, 0x8b, 0x04, 1, 0, 0, 0, 0, 2, 0]
+    );
+
+    // The flags word after the fields that depend on it, read from the end of each variant's
+    // nested buffer
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[repr(u8)]
+    #[binary(nest(u8))]
+    enum Trailer {
+        Entry(
+            #[binary(flags(0x01))] Option<u8>,
+            #[binary(flags(0x02))] Option<u8>,
+            #[binary(flags)] u8,
+        ),
+    }
+
+    roundtrip!(Trailer::Entry(None, Some(7), 0x82), vec![0, 2, 7, 0x82]);
+    roundtrip!(Trailer::Entry(Some(1), Some(2), 0x03), vec![0, 3, 1, 2, 3]);
+    assert_eq!(
+        binary::encode_to_bytes(Trailer::Entry(None, Some(7), 0x80)),
+        Ok(vec![0, 2, 7, 0x82])
+    );
+    assert_eq!(
+        binary::decode_from_bytes::<Trailer>(&[0, 3, 1, 2, 0x02]),
+        Err(binary::BinError::TrailingData(1))
+    );
+    assert_eq!(
+        binary::decode_from_bytes::<Trailer>(&[0, 0]),
+        Err(binary::BinError::InsufficientData)
+    );
+
+    // Only the nested buffer is read, so other data may follow
+    roundtrip!(
+        vec![
+            Trailer::Entry(Some(5), None, 0x01),
+            Trailer::Entry(None, None, 0),
+        ],
+        vec![0, 2, 5, 0x01, 0, 1, 0]
+    );
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[binary(nest(u8))]
+    struct Footer {
+        id: u8,
+        #[binary(flags(0x01), big)]
+        len: Option<u16>,
+        #[binary(flags)]
+        flags: u8,
+    }
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Record {
+        footer: Footer,
+        after: u8,
+    }
+
+    roundtrip!(
+        Record {
+            footer: Footer {
+                id: 1,
+                len: Some(0x0203),
+                flags: 0x01,
+            },
+            after: 9,
+        },
+        vec![4, 1, 2, 3, 0x01, 9]
+    );
+    let mut stream = &[2u8, 1, 0, 9, 8][..];
+    assert_eq!(
+        binary::decode_from_stream::<Record>(&mut stream),
+        Ok(Record {
+            footer: Footer {
+                id: 1,
+                len: None,
+                flags: 0,
+            },
+            after: 9,
+        })
+    );
+    assert_eq!(stream, &[8]);
+}
+
+#[test]
+fn test_variant_tags() {
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    enum Chunk {
+        #[binary(tag = b"IHDR")]
+        Header { width: u8, height: u8 },
+        #[binary(tag = b"IEND")]
+        End,
+        #[binary(tag = "b\"tEXt\" | b\"zTXt\"")]
+        Text(#[binary(tag)] [u8; 4], String),
+    }
+
+    roundtrip!(
+        Chunk::Header {
+            width: 1,
+            height: 2
+        },
+        vec![b'I', b'H', b'D', b'R', 1, 2]
+    );
+    roundtrip!(Chunk::End, vec![b'I', b'E', b'N', b'D']);
+    roundtrip!(
+        Chunk::Text(*b"zTXt", "a".to_string()),
+        vec![b'z', b'T', b'X', b't', b'a', 0]
+    );
+    assert_eq!(
+        binary::encode_to_bytes(Chunk::Text(*b"IEND", "a".to_string())),
+        Err(binary::BinError::TagMismatch("Text".to_string()))
+    );
+    assert_eq!(
+        binary::decode_from_bytes::<Chunk>(b"abcd"),
+        Err(binary::BinError::ByteVariantNotMatched(b"abcd".to_vec()))
+    );
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[binary(tag(u8))]
+    enum Op {
+        #[binary(tag = 1)]
+        Nop,
+        Push(u8),
+        #[binary(tag = "0x10..=0x1f")]
+        Short(#[binary(tag)] u8),
+        #[binary(tag = "0x20 | 0x22")]
+        Pair(u8),
+    }
+
+    roundtrip!(Op::Nop, vec![1]);
+    roundtrip!(Op::Push(5), vec![2, 5]);
+    roundtrip!(Op::Short(0x13), vec![0x13]);
+    roundtrip!(Op::Pair(5), vec![0x20, 5]);
+    assert_eq!(binary::decode_from_bytes(&[0x22, 5]), Ok(Op::Pair(5)));
+    assert_eq!(
+        binary::decode_from_bytes::<Op>(&[0x21]),
+        Err(binary::BinError::VariantNotMatched(0x21))
+    );
+}
+
+#[test]
+fn test_const_tags() {
+    const BASE: i8 = 0x20;
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[repr(i8)]
+    enum Level {
+        Below = -2,
+        Under,
+        Shifted = 1 << 4,
+        Based = BASE,
+        Next,
+    }
+
+    roundtrip!(Level::Below, vec![0xfe]);
+    roundtrip!(Level::Under, vec![0xff]);
+    roundtrip!(Level::Shifted, vec![0x10]);
+    roundtrip!(Level::Based, vec![0x20]);
+    roundtrip!(Level::Next, vec![0x21]);
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[binary(tag(i16))]
+    enum Delta {
+        #[binary(tag = "-1")]
+        Down,
+        #[binary(tag = "BASE as i16 ..= BASE as i16 + 3")]
+        Up(#[binary(tag)] i16),
+    }
+
+    roundtrip!(Delta::Down, vec![0xff, 0xff]);
+    roundtrip!(Delta::Up(0x22), vec![0x22, 0]);
+    assert_eq!(
+        binary::decode_from_bytes::<Delta>(&[0xfe, 0xff]),
+        Err(binary::BinError::VariantNotMatched(-2i64 as u64))
+    );
+}
+
+#[test]
+fn test_external_tag() {
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[repr(u8)]
+    enum Payload {
+        Ping = 1,
+        Data(u16) = 2,
+    }
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Message {
+        msg_type: u8,
+        seq: u8,
+        #[binary(tag = "self.msg_type")]
+        payload: Payload,
+    }
+
+    roundtrip!(
+        Message {
+            msg_type: 1,
+            seq: 7,
+            payload: Payload::Ping,
+        },
+        vec![1, 7]
+    );
+    roundtrip!(
+        Message {
+            msg_type: 2,
+            seq: 7,
+            payload: Payload::Data(0x1234),
+        },
+        vec![2, 7, 0x34, 0x12]
+    );
+    assert_eq!(
+        binary::encode_to_bytes(Message {
+            msg_type: 1,
+            seq: 7,
+            payload: Payload::Data(0x1234),
+        }),
+        Err(binary::BinError::TagMismatch("Data".to_string()))
+    );
+    assert_eq!(binary::BinSerializeTagged::tag(&Payload::Data(0)), 2);
+}
+
 #[test]
 fn test_default_variant() {
     #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
@@ -223,11 +875,71 @@ fn test_default_variant() {
     );
 }
 
-#[derive(BinSerialize, BinDeserialize, PartialEq, Eq, Debug)]
-struct TestStruct {
-    #[binary(len(u8))]
-    x: Vec<u8>,
-}
+#[test]
+fn test_default_variant_shapes() {
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[repr(u8)]
+    enum Color {
+        Red = 1,
+        Green = 2,
+        #[binary(default)]
+        Unknown(u8),
+    }
+
+    roundtrip!(Color::Green, vec![2]);
+    roundtrip!(Color::Unknown(9), vec![9]);
+    assert_eq!(
+        binary::encode_to_bytes(Color::Unknown(1)),
+        Err(binary::BinError::TagMismatch("Unknown".to_string()))
+    );
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[binary(tag(u8), nest(u8))]
+    enum Record {
+        A(u8),
+        #[binary(default)]
+        Unknown {
+            payload: Vec<u8>,
+            #[binary(tag)]
+            kind: u8,
+        },
+    }
+
+    // The nested length keeps an unknown record from consuming what follows it
+    assert_eq!(
+        binary::decode_from_bytes(&[7, 2, 0xaa, 0xbb, 0, 1, 5]),
+        Ok(vec![
+            Record::Unknown {
+                payload: vec![0xaa, 0xbb],
+                kind: 7
+            },
+            Record::A(5)
+        ])
+    );
+    roundtrip!(
+        Record::Unknown {
+            payload: vec![0xaa],
+            kind: 7
+        },
+        vec![7, 1, 0xaa]
+    );
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[binary(tag(u8))]
+    enum Versioned {
+        V1(u8),
+        #[binary(default)]
+        Later(u8, u16),
+    }
+
+    roundtrip!(Versioned::Later(3, 0x1234), vec![3, 0x34, 0x12]);
+}
+
+#[derive(BinSerialize, BinDeserialize, PartialEq, Eq, Debug)]
+struct TestStruct {
+    #[binary(len(u8))]
+    x: Vec<u8>,
+}
 
 #[test]
 fn test_encode_error() {
@@ -246,3 +958,983 @@ fn test_decode_error() {
         Err(binary::BinError::InsufficientData)
     );
 }
+
+mod ipv4 {
+    use binary::attr::Attrs;
+    use binary::{BinRead, BinWrite, Result};
+    use std::net::Ipv4Addr;
+
+    pub fn encode(v: &Ipv4Addr, buf: &mut dyn BinWrite, _attrs: Attrs) -> Result<()> {
+        buf.write_all(&v.octets())?;
+        Ok(())
+    }
+
+    pub fn decode(buf: &mut dyn BinRead, _attrs: Attrs) -> Result<Ipv4Addr> {
+        let mut octets = [0u8; 4];
+        buf.read_exact(&mut octets)?;
+        Ok(Ipv4Addr::from(octets))
+    }
+}
+
+fn encode_doubled(
+    v: &u8,
+    buf: &mut dyn binary::BinWrite,
+    attrs: binary::attr::Attrs,
+) -> binary::Result<()> {
+    BinSerialize::encode_to(&(*v as u16 * 2), buf, attrs)
+}
+
+fn decode_halved(buf: &mut dyn binary::BinRead, attrs: binary::attr::Attrs) -> binary::Result<u8> {
+    let v: u16 = BinDeserialize::decode_from(buf, attrs)?;
+    Ok((v / 2) as u8)
+}
+
+#[test]
+fn test_with() {
+    use std::net::Ipv4Addr;
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct With {
+        #[binary(with = "ipv4")]
+        addr: Ipv4Addr,
+        #[binary(
+            big,
+            serialize_with = "encode_doubled",
+            deserialize_with = "decode_halved"
+        )]
+        doubled: u8,
+    }
+
+    roundtrip!(
+        With {
+            addr: Ipv4Addr::new(127, 0, 0, 1),
+            doubled: 200,
+        },
+        vec![127, 0, 0, 1, 1, 144]
+    );
+}
+
+#[test]
+fn test_expression_attrs() {
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[binary(assert(self.version < 8, "unknown version"))]
+    struct Header {
+        version: u8,
+        #[binary(if = self.version >= 3, align = 1 << 1)]
+        extra: Option<u16>,
+        #[binary(flags)]
+        flags: u8,
+        #[binary(flags(mask = 0x3 << 4, eq = 1 << 4))]
+        a: Option<u8>,
+    }
+
+    roundtrip!(
+        Header {
+            version: 3,
+            extra: Some(1000),
+            flags: 0x10,
+            a: Some(9)
+        },
+        vec![3, 0, 232, 3, 0x10, 9]
+    );
+    assert!(matches!(
+        binary::decode_from_bytes::<Header>(&[9, 0, 232, 3, 0]),
+        Err(binary::BinError::ValidationFailed { .. })
+    ));
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[binary(tag(i8))]
+    enum Step {
+        #[binary(tag = -1)]
+        Back,
+        #[binary(tag = 1..=3)]
+        Forward(#[binary(tag)] i8),
+    }
+
+    roundtrip!(Step::Back, vec![0xff]);
+    roundtrip!(Step::Forward(2), vec![2]);
+}
+
+#[test]
+fn test_condition() {
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Versioned {
+        version: u8,
+        #[binary(if = "self.version >= 3")]
+        extra: Option<u16>,
+    }
+
+    roundtrip!(
+        Versioned {
+            version: 2,
+            extra: None
+        },
+        vec![2]
+    );
+    roundtrip!(
+        Versioned {
+            version: 3,
+            extra: Some(1000)
+        },
+        vec![3, 232, 3]
+    );
+    assert_eq!(
+        binary::encode_to_bytes(Versioned {
+            version: 2,
+            extra: Some(1000)
+        }),
+        Err(binary::BinError::ConditionMismatch("extra".to_string()))
+    );
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[binary(tag(u8))]
+    enum Message {
+        Kind(u8, #[binary(if = "self.0 == 7")] Option<String>),
+    }
+
+    roundtrip!(Message::Kind(1, None), vec![0, 1]);
+    roundtrip!(
+        Message::Kind(7, Some("test".to_string())),
+        vec![0, 7, 116, 101, 115, 116, 0]
+    );
+    assert_eq!(
+        binary::encode_to_bytes(Message::Kind(7, None)),
+        Err(binary::BinError::ConditionMismatch("1".to_string()))
+    );
+}
+
+#[test]
+fn test_padding() {
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[binary(align = 4)]
+    struct Padded {
+        a: u8,
+        #[binary(align = 4)]
+        b: u16,
+        #[binary(pad_before = 1, pad_after = 2, fill = 0xff)]
+        c: u8,
+    }
+
+    roundtrip!(
+        Padded { a: 1, b: 2, c: 3 },
+        vec![1, 0, 0, 0, 2, 0, 0xff, 3, 0xff, 0xff, 0, 0]
+    );
+
+    // Alignment counts from the start of the struct, wherever it falls in the stream, unless the
+    // field holding it is aligned too
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Inner {
+        a: u8,
+        #[binary(align = 4)]
+        b: u16,
+    }
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Outer {
+        id: u8,
+        inner: Inner,
+        #[binary(align = 4)]
+        aligned: Inner,
+    }
+
+    roundtrip!(
+        Outer {
+            id: 9,
+            inner: Inner { a: 1, b: 2 },
+            aligned: Inner { a: 3, b: 4 },
+        },
+        vec![9, 1, 0, 0, 0, 2, 0, 0, 3, 0, 0, 0, 4, 0]
+    );
+
+    // A variant's fields align from the start of the enum, before its tag, unless the variant is
+    // nested, when they align from the start of its buffer
+    #[derive(BinSerialize, BinDeserialize, binary::BinSize, Debug, PartialEq, Eq)]
+    #[repr(u8)]
+    enum Aligned {
+        A(u8, #[binary(align = 4)] u32),
+        #[binary(nest(u8))]
+        B(u8, #[binary(align = 4)] u32),
+    }
+
+    roundtrip!(Aligned::A(1, 2), vec![0, 1, 0, 0, 2, 0, 0, 0]);
+    roundtrip!(Aligned::B(1, 2), vec![1, 8, 1, 0, 0, 0, 2, 0, 0, 0]);
+    assert_eq!(<Aligned as binary::BinSize>::MIN_SIZE, 8);
+    assert_eq!(<Aligned as binary::BinSize>::MAX_SIZE, Some(10));
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[binary(check_pad, fill = 0x20)]
+    struct Checked(u8, #[binary(pad_before = 2)] u8);
+
+    roundtrip!(Checked(1, 2), vec![1, 0x20, 0x20, 2]);
+    assert_eq!(
+        binary::decode_from_bytes::<Checked>(&[1, 0x20, 0, 2]),
+        Err(binary::BinError::InvalidPadding(0))
+    );
+}
+
+#[test]
+fn test_stream_alignment() {
+    #[derive(BinSerialize, BinDeserialize, binary::BinSize, Debug, PartialEq, Eq)]
+    struct Inner {
+        b: u8,
+        #[binary(align(stream = 4))]
+        c: u16,
+        #[binary(align = 4)]
+        d: u8,
+    }
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Outer {
+        a: u8,
+        #[binary(nest(u8))]
+        inner: Inner,
+    }
+
+    roundtrip!(Inner { b: 2, c: 3, d: 4 }, vec![2, 0, 0, 0, 3, 0, 0, 0, 4]);
+    // c is aligned from the start of the stream, through the nested buffer, and d from the start
+    // of Inner
+    roundtrip!(
+        Outer {
+            a: 1,
+            inner: Inner { b: 2, c: 3, d: 4 }
+        },
+        vec![1, 5, 2, 0, 3, 0, 4]
+    );
+    assert_eq!(<Inner as binary::BinSize>::MIN_SIZE, 5);
+    assert_eq!(<Inner as binary::BinSize>::MAX_SIZE, Some(9));
+
+    let mut stream: &[u8] = &[1, 5, 2, 0, 3, 0, 4, 9];
+    assert_eq!(
+        binary::decode_from_stream(&mut stream),
+        Ok(Outer {
+            a: 1,
+            inner: Inner { b: 2, c: 3, d: 4 }
+        })
+    );
+    assert_eq!(stream, [9]);
+
+    // Each element of a Vec is padded to suit where it falls in the stream
+    roundtrip!(
+        vec![Inner { b: 1, c: 2, d: 3 }, Inner { b: 4, c: 5, d: 6 }],
+        vec![1, 0, 0, 0, 2, 0, 0, 0, 3, 4, 0, 0, 5, 0, 0, 0, 0, 6]
+    );
+
+    // Outside of the top-level functions, the position in the stream is not known
+    assert_eq!(
+        Inner::decode_from(
+            &mut &[2, 0, 0, 0, 3, 0, 0, 0, 4][..],
+            binary::attr::Attrs::zero()
+        ),
+        Err(binary::BinError::UnknownStreamPosition)
+    );
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Blob<'a> {
+        #[binary(len(u8))]
+        name: &'a [u8],
+        #[binary(align(stream = 4))]
+        v: u16,
+    }
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Pair<'a> {
+        a: u8,
+        #[binary(nest(u8))]
+        blob: Blob<'a>,
+    }
+
+    roundtrip!(
+        Pair {
+            a: 1,
+            blob: Blob { name: b"xy", v: 2 }
+        },
+        vec![1, 8, 2, b'x', b'y', 0, 0, 0, 2, 0]
+    );
+}
+
+fn check_even(v: &u8) -> Result<(), String> {
+    if v & 1 == 0 {
+        Ok(())
+    } else {
+        Err(format!("{} is odd", v))
+    }
+}
+
+#[test]
+fn test_validation() {
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[binary(assert("self.ihl >= 5", "header too short"))]
+    struct Header {
+        #[binary(assert("self.version <= 4"))]
+        version: u8,
+        ihl: u8,
+        #[binary(validate = "check_even", check_encode)]
+        even: u8,
+    }
+
+    roundtrip!(
+        Header {
+            version: 4,
+            ihl: 5,
+            even: 2
+        },
+        vec![4, 5, 2]
+    );
+    assert_eq!(
+        binary::decode_from_bytes::<Header>(&[5, 5, 2]),
+        Err(binary::BinError::ValidationFailed {
+            field: "version".to_string(),
+            message: "assertion failed: self.version <= 4".to_string(),
+        })
+    );
+    assert_eq!(
+        binary::decode_from_bytes::<Header>(&[4, 4, 2]),
+        Err(binary::BinError::ValidationFailed {
+            field: "Header".to_string(),
+            message: "header too short".to_string(),
+        })
+    );
+    assert_eq!(
+        binary::decode_from_bytes::<Header>(&[4, 5, 3]),
+        Err(binary::BinError::ValidationFailed {
+            field: "even".to_string(),
+            message: "3 is odd".to_string(),
+        })
+    );
+    // only `even` asks for its checks to run on encode
+    assert_eq!(
+        binary::encode_to_bytes(Header {
+            version: 5,
+            ihl: 4,
+            even: 3
+        }),
+        Err(binary::BinError::ValidationFailed {
+            field: "even".to_string(),
+            message: "3 is odd".to_string(),
+        })
+    );
+    assert_eq!(
+        binary::encode_to_bytes(Header {
+            version: 5,
+            ihl: 4,
+            even: 2
+        }),
+        Ok(vec![5, 4, 2])
+    );
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Request,
+    Response,
+}
+
+impl TryFrom<u8> for Kind {
+    type Error = String;
+    fn try_from(v: u8) -> Result<Self, String> {
+        match v {
+            1 => Ok(Kind::Request),
+            2 => Ok(Kind::Response),
+            v => Err(format!("unknown kind {}", v)),
+        }
+    }
+}
+
+impl From<Kind> for u8 {
+    fn from(k: Kind) -> u8 {
+        match k {
+            Kind::Request => 1,
+            Kind::Response => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Celsius(i16);
+
+fn from_tenths(v: i16) -> Celsius {
+    Celsius(v / 10)
+}
+
+impl From<Celsius> for i16 {
+    fn from(c: Celsius) -> i16 {
+        c.0 * 10
+    }
+}
+
+#[test]
+fn test_conversions() {
+    use std::num::NonZeroU32;
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Converted {
+        #[binary(try_from = "u8", into = "u8")]
+        kind: Kind,
+        #[binary(try_from = "u32", into = "u32")]
+        id: NonZeroU32,
+        #[binary(map = "from_tenths", into = "i16")]
+        temperature: Celsius,
+    }
+
+    roundtrip!(
+        Converted {
+            kind: Kind::Response,
+            id: NonZeroU32::new(7).unwrap(),
+            temperature: Celsius(-4),
+        },
+        vec![2, 7, 0, 0, 0, 216, 255]
+    );
+    assert_eq!(
+        binary::decode_from_bytes::<Converted>(&[3, 7, 0, 0, 0, 216, 255]),
+        Err(binary::BinError::ConversionFailed {
+            field: "kind".to_string(),
+            message: "unknown kind 3".to_string(),
+        })
+    );
+    assert_eq!(
+        binary::decode_from_bytes::<Converted>(&[1, 0, 0, 0, 0, 216, 255]),
+        Err(binary::BinError::ConversionFailed {
+            field: "id".to_string(),
+            message: NonZeroU32::try_from(0u32).unwrap_err().to_string(),
+        })
+    );
+}
+
+#[test]
+fn test_args() {
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[binary(import = "version: u8, big: bool")]
+    struct Body {
+        a: u8,
+        #[binary(if = "version >= 2")]
+        b: Option<u16>,
+        #[binary(assert("!big || self.a > 0"))]
+        c: u8,
+    }
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Message {
+        version: u8,
+        #[binary(args = "self.version, self.version > 3")]
+        body: Body,
+    }
+
+    roundtrip!(
+        Message {
+            version: 1,
+            body: Body {
+                a: 1,
+                b: None,
+                c: 3
+            }
+        },
+        vec![1, 1, 3]
+    );
+    roundtrip!(
+        Message {
+            version: 2,
+            body: Body {
+                a: 1,
+                b: Some(2),
+                c: 3
+            }
+        },
+        vec![2, 1, 2, 0, 3]
+    );
+    assert_eq!(
+        binary::decode_from_bytes::<Message>(&[4, 0, 2, 0, 3]),
+        Err(binary::BinError::ValidationFailed {
+            field: "c".to_string(),
+            message: "assertion failed: !big || self.a > 0".to_string(),
+        })
+    );
+
+    roundtrip_with!(
+        Body {
+            a: 1,
+            b: Some(2),
+            c: 3
+        },
+        (2, false),
+        vec![1, 2, 0, 3]
+    );
+
+    // The list forms, with an import whose type holds a comma
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    #[binary(import(count: u8, names: std::collections::BTreeMap<u8, String>))]
+    struct Entry {
+        #[binary(if = count > 0)]
+        first: Option<u8>,
+        #[binary(assert(names.contains_key(&self.id), "unknown id"))]
+        id: u8,
+    }
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Header {
+        count: u8,
+        #[binary(args(self.count, vec![(1, "one".to_string())].into_iter().collect()))]
+        entry: Entry,
+    }
+
+    roundtrip!(
+        Header {
+            count: 2,
+            entry: Entry {
+                first: Some(5),
+                id: 1,
+            }
+        },
+        vec![2, 5, 1]
+    );
+
+    let names: std::collections::BTreeMap<u8, String> =
+        vec![(1, "one".to_string())].into_iter().collect();
+    let entry = Entry { first: None, id: 1 };
+    let mut buf = vec![];
+    binary::encode_to_stream_with(&entry, &mut buf, (0, names.clone())).unwrap();
+    assert_eq!(buf, vec![1]);
+    let decoded: Entry =
+        binary::decode_from_stream_with(&mut &buf[..], (0, names.clone())).unwrap();
+    assert_eq!(decoded, entry);
+    assert_eq!(
+        binary::decode_from_stream_with::<Entry>(&mut &[2u8][..], (0, names)),
+        Err(binary::BinError::ValidationFailed {
+            field: "id".to_string(),
+            message: "unknown id".to_string(),
+        })
+    );
+}
+
+// A MIDI-style status byte, which is omitted when it repeats the previous event's status
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RunningStatus(u8);
+
+impl BinSerialize for RunningStatus {
+    fn encode_to(
+        &self,
+        buf: &mut dyn binary::BinWrite,
+        _attrs: binary::attr::Attrs,
+    ) -> binary::Result<()> {
+        let previous = binary::session::with(|s| s.insert(*self)).flatten();
+        if previous != Some(*self) {
+            buf.put_u8(self.0)?;
+        }
+        Ok(())
+    }
+}
+
+impl BinDeserialize for RunningStatus {
+    fn decode_from(
+        buf: &mut dyn binary::BinRead,
+        _attrs: binary::attr::Attrs,
+    ) -> binary::Result<Self> {
+        let next = *buf
+            .fill_buf()?
+            .first()
+            .ok_or(binary::BinError::InsufficientData)?;
+        if next & 0x80 != 0 {
+            buf.consume(1);
+            binary::session::with(|s| s.insert(RunningStatus(next)));
+            return Ok(RunningStatus(next));
+        }
+        binary::session::with(|s| s.get::<RunningStatus>().copied())
+            .flatten()
+            .ok_or_else(|| binary::BinError::Custom("no running status".to_string()))
+    }
+}
+
+#[test]
+fn test_session() {
+    use binary::session::{self, Session};
+
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Event {
+        status: RunningStatus,
+        data: u8,
+    }
+
+    let events = vec![
+        Event {
+            status: RunningStatus(0x90),
+            data: 1,
+        },
+        Event {
+            status: RunningStatus(0x90),
+            data: 2,
+        },
+        Event {
+            status: RunningStatus(0x80),
+            data: 3,
+        },
+    ];
+    let bytes = vec![0x90, 1, 2, 0x80, 3];
+
+    let mut s = Session::new();
+    assert_eq!(
+        session::enter(&mut s, || binary::encode_to_bytes(&events)),
+        Ok(bytes.clone())
+    );
+    assert_eq!(s.get::<RunningStatus>(), Some(&RunningStatus(0x80)));
+
+    let mut s = Session::new();
+    assert_eq!(
+        session::enter(&mut s, || binary::decode_from_bytes::<Vec<Event>>(&bytes)),
+        Ok(events)
+    );
+
+    assert_eq!(
+        binary::decode_from_bytes::<Event>(&[1]),
+        Err(binary::BinError::Custom("no running status".to_string()))
+    );
+
+    // An error part way through leaves what was stored so far, and no session active
+    let mut s = Session::new();
+    assert_eq!(
+        session::enter(&mut s, || binary::decode_from_bytes::<[Event; 3]>(&[
+            0x90, 1, 2, 0x85
+        ])),
+        Err(binary::BinError::InsufficientData)
+    );
+    assert_eq!(s.get::<RunningStatus>(), Some(&RunningStatus(0x85)));
+    assert_eq!(session::with(|_| ()), None);
+}
+
+#[test]
+fn test_session_expressions() {
+    use binary::session::{self, Session};
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    struct Version(u8);
+
+    // A record whose layout depends on a version set in the session by its container
+    #[derive(BinSerialize, BinDeserialize, Debug, PartialEq, Eq)]
+    struct Record {
+        id: u8,
+        #[binary(if = session::get::<Version>().map_or(false, |v| v.0 >= 2))]
+        flags: Option<u8>,
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct File {
+        version: Version,
+        records: Vec<Record>,
+    }
+
+    impl BinSerialize for File {
+        fn encode_to(
+            &self,
+            buf: &mut dyn binary::BinWrite,
+            attrs: binary::attr::Attrs,
+        ) -> binary::Result<()> {
+            buf.put_u8(self.version.0)?;
+            session::with(|s| s.insert(self.version));
+            self.records.encode_to(buf, attrs)
+        }
+    }
+
+    impl BinDeserialize for File {
+        fn decode_from(
+            buf: &mut dyn binary::BinRead,
+            attrs: binary::attr::Attrs,
+        ) -> binary::Result<Self> {
+            let version = Version(buf.get_u8()?);
+            session::with(|s| s.insert(version));
+            let records = BinDeserialize::decode_from(buf, attrs)?;
+            Ok(File { version, records })
+        }
+    }
+
+    let v1 = File {
+        version: Version(1),
+        records: vec![Record { id: 1, flags: None }, Record { id: 2, flags: None }],
+    };
+    let v2 = File {
+        version: Version(2),
+        records: vec![Record {
+            id: 1,
+            flags: Some(7),
+        }],
+    };
+    for (file, bytes) in [(v1, vec![1, 1, 2]), (v2, vec![2, 1, 7])] {
+        let mut s = Session::new();
+        assert_eq!(
+            session::enter(&mut s, || binary::encode_to_bytes(&file)),
+            Ok(bytes.clone())
+        );
+        let mut s = Session::new();
+        assert_eq!(
+            session::enter(&mut s, || binary::decode_from_bytes::<File>(&bytes)),
+            Ok(file)
+        );
+        assert_eq!(s.get::<Version>(), Some(&Version(bytes[0])));
+    }
+
+    // A nested session hides the outer one until it ends, even if it ends in an error
+    let mut outer = Session::new();
+    outer.insert(Version(2));
+    session::enter(&mut outer, || {
+        let mut inner = Session::new();
+        inner.insert(Version(1));
+        let record = session::enter(&mut inner, || {
+            assert_eq!(session::get::<Version>(), Some(Version(1)));
+            binary::decode_from_bytes::<Record>(&[3, 9])
+        });
+        assert_eq!(record, Ok(Record { id: 3, flags: None }));
+        let mut failing = Session::new();
+        assert_eq!(
+            session::enter(&mut failing, || binary::decode_from_bytes::<u16>(&[1])),
+            Err(binary::BinError::InsufficientData)
+        );
+        assert_eq!(session::get::<Version>(), Some(Version(2)));
+        binary::decode_from_bytes::<Record>(&[3, 9])
+    })
+    .unwrap();
+    assert_eq!(outer.get::<Version>(), Some(&Version(2)));
+    assert_eq!(session::get::<Version>(), None);
+}
+
+#[test]
+fn test_union() {
+    #[derive(BinSerialize, BinDeserialize, Clone, Copy)]
+    #[repr(C)]
+    // Safety: every Value is encoded with the kind of the member it was built with
+    #[binary(import = "kind: u8", unsafe_select = "kind")]
+    union Value {
+        #[binary(tag = 1)]
+        int: u32,
+        #[binary(tag = 2, big)]
+        short: u16,
+    }
+
+    #[derive(BinSerialize, BinDeserialize, Clone, Copy)]
+    struct Tagged {
+        kind: u8,
+        #[binary(args = "self.kind")]
+        value: Value,
+    }
+
+    let int = Tagged {
+        kind: 1,
+        value: Value { int: 0x11223344 },
+    };
+    assert_eq!(
+        binary::encode_to_bytes(int),
+        Ok(vec![1, 0x44, 0x33, 0x22, 0x11])
+    );
+    let decoded: Tagged = binary::decode_from_bytes(&[1, 0x44, 0x33, 0x22, 0x11]).unwrap();
+    assert_eq!(unsafe { decoded.value.int }, 0x11223344);
+
+    let short = Tagged {
+        kind: 2,
+        value: Value { short: 0x1122 },
+    };
+    assert_eq!(binary::encode_to_bytes(short), Ok(vec![2, 0x11, 0x22]));
+    let decoded: Tagged = binary::decode_from_bytes(&[2, 0x11, 0x22]).unwrap();
+    assert_eq!(unsafe { decoded.value.short }, 0x1122);
+
+    assert!(matches!(
+        binary::decode_from_bytes::<Tagged>(&[3, 0]),
+        Err(binary::BinError::VariantNotMatched(3))
+    ));
+
+    #[derive(BinSerialize, BinDeserialize, Clone, Copy)]
+    #[repr(C)]
+    #[binary(raw)]
+    union Raw {
+        bytes: [u8; 4],
+        word: u32,
+    }
+
+    assert_eq!(
+        binary::encode_to_bytes(Raw {
+            bytes: [1, 2, 3, 4]
+        }),
+        Ok(vec![1, 2, 3, 4])
+    );
+    let decoded: Raw = binary::decode_from_bytes(&[1, 0, 0, 0]).unwrap();
+    assert_eq!(unsafe { decoded.word }, u32::from_ne_bytes([1, 0, 0, 0]));
+}
+
+#[test]
+fn test_view() {
+    #[derive(BinSerialize, BinDeserialize, binary::BinView, Debug, PartialEq, Clone, Copy)]
+    #[binary(big)]
+    struct Point {
+        x: i16,
+        y: i16,
+    }
+
+    #[derive(BinSerialize, BinDeserialize, binary::BinView, Debug, PartialEq, Clone, Copy)]
+    struct Header {
+        kind: u8,
+        #[binary(align = 4)]
+        len: u32,
+        #[binary(big)]
+        id: u16,
+        #[binary(pad_before = 1)]
+        origin: Point,
+        flags: [u16; 2],
+        ok: bool,
+    }
+
+    let header = Header {
+        kind: 7,
+        len: 0x01020304,
+        id: 0x0506,
+        origin: Point { x: -2, y: 3 },
+        flags: [1, 0x0100],
+        ok: true,
+    };
+    let bytes = vec![
+        7, 0, 0, 0, 4, 3, 2, 1, 5, 6, 0, 0xff, 0xfe, 0, 3, 1, 0, 0, 1, 1,
+    ];
+    roundtrip!(header, bytes.clone());
+    assert_eq!(<Header as binary::BinViewField>::SIZE, bytes.len());
+
+    let mut long = bytes.clone();
+    long.push(9);
+    let view = HeaderView::new(&long).unwrap();
+    assert_eq!(view.as_bytes(), &bytes[..]);
+    assert_eq!(view.kind(), 7);
+    assert_eq!(view.len(), 0x01020304);
+    assert_eq!(view.id(), 0x0506);
+    assert_eq!(view.origin(), Point { x: -2, y: 3 });
+    assert_eq!(view.flags(), [1, 0x0100]);
+    assert!(view.ok());
+    assert_eq!(view.get(), header);
+
+    assert!(matches!(
+        HeaderView::new(&bytes[..bytes.len() - 1]),
+        Err(binary::BinError::InsufficientData)
+    ));
+
+    let mut buf = bytes.clone();
+    let mut view = HeaderViewMut::new(&mut buf).unwrap();
+    view.set_len(0x0a0b0c0d);
+    view.set_origin(Point { x: 1, y: -1 });
+    view.set_ok(false);
+    assert_eq!(view.len(), 0x0a0b0c0d);
+    assert_eq!(
+        buf,
+        vec![7, 0, 0, 0, 0x0d, 0x0c, 0x0b, 0x0a, 5, 6, 0, 0, 1, 0xff, 0xff, 1, 0, 0, 1, 0]
+    );
+    let decoded: Header = binary::decode_from_bytes(&buf).unwrap();
+    assert_eq!(
+        decoded,
+        Header {
+            len: 0x0a0b0c0d,
+            origin: Point { x: 1, y: -1 },
+            ok: false,
+            ..header
+        }
+    );
+
+    let mut view = HeaderViewMut::new(&mut buf).unwrap();
+    view.set(&header);
+    assert_eq!(buf, bytes);
+
+    let point = PointView::new(&[0, 1, 0, 2]).unwrap();
+    assert_eq!((point.x(), point.y()), (1, 2));
+}
+
+#[test]
+fn test_size() {
+    use binary::BinSize;
+
+    assert_eq!(u16::FIXED_SIZE, Some(2));
+    assert_eq!(<[u32; 3]>::FIXED_SIZE, Some(12));
+    assert_eq!(String::MIN_SIZE, 1);
+    assert_eq!(<Vec<u8>>::MAX_SIZE, None);
+
+    #[derive(BinSerialize, BinSize)]
+    #[binary(size = 16)]
+    struct Header {
+        kind: u8,
+        #[binary(align = 4)]
+        len: u32,
+        #[binary(pad_before = 2)]
+        id: [u16; 2],
+        #[binary(pad_after = 1)]
+        ok: bool,
+    }
+
+    assert_eq!(Header::FIXED_SIZE, Some(16));
+    let header = Header {
+        kind: 1,
+        len: 2,
+        id: [3, 4],
+        ok: true,
+    };
+    assert_eq!(binary::encode_to_bytes(header).unwrap().len(), 16);
+
+    #[derive(BinSerialize, BinSize)]
+    #[binary(nest(u16))]
+    struct Packet {
+        #[binary(flags)]
+        flags: u8,
+        #[binary(flags(0x01))]
+        checksum: Option<u32>,
+        #[binary(len(u8))]
+        body: Vec<u16>,
+    }
+
+    assert_eq!(Packet::MIN_SIZE, 4);
+    assert_eq!(Packet::MAX_SIZE, None);
+    assert_eq!(Packet::FIXED_SIZE, None);
+
+    #[derive(BinSerialize, BinSize)]
+    #[repr(u8)]
+    enum Command {
+        Stop,
+        Move(i16, i16),
+        #[binary(nest(u8))]
+        Say([u8; 4]),
+    }
+
+    assert_eq!(Command::MIN_SIZE, 1);
+    assert_eq!(Command::MAX_SIZE, Some(6));
+    assert_eq!(
+        binary::encode_to_bytes(Command::Say(*b"hey!"))
+            .unwrap()
+            .len(),
+        6
+    );
+    assert_eq!(binary::encode_to_bytes(Command::Stop).unwrap().len(), 1);
+    assert_eq!(
+        binary::encode_to_bytes(Command::Move(1, 2)).unwrap().len(),
+        5
+    );
+
+    // The command's tag is the message's kind, so only its fields are counted
+    #[derive(BinSerialize, BinSize)]
+    struct Message {
+        kind: u8,
+        #[binary(tag = "self.kind")]
+        command: Command,
+    }
+
+    assert_eq!(Message::MIN_SIZE, 1);
+    assert_eq!(Message::MAX_SIZE, Some(6));
+
+    #[derive(BinSerialize, BinSize)]
+    #[binary(size = 5)]
+    struct Step {
+        kind: u8,
+        #[binary(tag = "self.kind")]
+        step: Move,
+    }
+
+    #[derive(BinSerialize, BinSize)]
+    #[repr(u8)]
+    enum Move {
+        Walk(i16, i16) = 1,
+        Run(u32) = 2,
+    }
+
+    assert_eq!(Step::FIXED_SIZE, Some(5));
+    for step in [Move::Walk(1, 2), Move::Run(3)] {
+        let kind = binary::BinSerializeTagged::tag(&step);
+        let bytes = binary::encode_to_bytes(Step { kind, step }).unwrap();
+        assert_eq!(bytes.len(), 5);
+    }
+}
@@ -40,8 +40,12 @@ pub(crate) enum Level {
 
 pub(crate) struct Context {
     pub(crate) env: Environment,
+    pub(crate) name: String, // the type, variant or field at this level, as shown in errors
     pub(crate) attrs: TokenStream2,
     pub(crate) self_attrs: SelfAttrs,
+    // Whether the type has lifetime parameters, and so is decoded as BinDeserializeBorrowed<'de>,
+    // borrowing from its input
+    pub(crate) borrowed: bool,
 }
 
 impl Context {
@@ -49,29 +53,35 @@ impl Context {
         let env = Environment::from_data(&input.data);
         let initial = Self {
             env,
+            name: String::new(),
             attrs: quote! {},
-            self_attrs: SelfAttrs {
-                tag_ty: None,
-                tag_le: None,
-                tag_default: false,
-                nest: false,
-                nest_variants: false,
-                nest_ty: None,
-                nest_le: None,
-                flags: false,
-                flag_value: None,
-            },
+            self_attrs: SelfAttrs::default(),
+            borrowed: input.generics.lifetimes().next().is_some(),
         };
 
-        initial.recurse_into(Level::Top, &input.attrs)
+        initial.recurse_into(Level::Top, input.ident.to_string(), &input.attrs)
     }
-    pub(crate) fn recurse_into(&self, level: Level, attrs: &[Attribute]) -> (Self, TokenStream2) {
+    pub(crate) fn recurse_into(
+        &self,
+        level: Level,
+        name: String,
+        attrs: &[Attribute],
+    ) -> (Self, TokenStream2) {
         let old_attrs = &self.attrs;
 
         let (attrs, mut self_attrs, attr_errors) =
             crate::helpers::parse_attrs(attrs, (self.env, level));
 
-        if self.self_attrs.nest_variants {
+        if self_attrs.fill.is_none() {
+            self_attrs.fill = self.self_attrs.fill;
+        }
+        if self_attrs.len.is_none() {
+            self_attrs.len = self.self_attrs.len;
+        }
+        self_attrs.check_pad |= self.self_attrs.check_pad;
+        self_attrs.check_encode |= self.self_attrs.check_encode;
+
+        if self.self_attrs.nest_variants && !self_attrs.nest && !self_attrs.nest_off {
             self_attrs.nest = true;
             self_attrs.nest_ty = self.self_attrs.nest_ty;
             self_attrs.nest_le = self.self_attrs.nest_le;
@@ -80,8 +90,10 @@ impl Context {
         (
             Self {
                 env: self.env,
+                name,
                 attrs: quote! { #old_attrs #attrs },
                 self_attrs,
+                borrowed: self.borrowed,
             },
             attr_errors,
         )
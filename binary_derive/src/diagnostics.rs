@@ -0,0 +1,440 @@
+use proc_macro2::Span;
+use syn::export::TokenStream2;
+use syn::spanned::Spanned;
+use syn::Meta;
+
+use crate::context::{Environment, Level};
+
+// How an attribute is written inside #[binary(...)]
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub(crate) enum Form {
+    Word,  // name
+    List,  // name(...)
+    Value, // name = ...
+}
+
+impl Form {
+    pub(crate) fn of(meta: &Meta) -> Self {
+        match meta {
+            Meta::Word(_) => Form::Word,
+            Meta::List(_) => Form::List,
+            Meta::NameValue(_) => Form::Value,
+        }
+    }
+}
+
+type Target = (Environment, Level);
+
+const STRUCT: Target = (Environment::Struct, Level::Top);
+const STRUCT_FIELD: Target = (Environment::Struct, Level::Field);
+const ENUM: Target = (Environment::Enum, Level::Top);
+const VARIANT: Target = (Environment::Enum, Level::Variant);
+const VARIANT_FIELD: Target = (Environment::Enum, Level::Field);
+const UNION: Target = (Environment::Union, Level::Top);
+const UNION_MEMBER: Target = (Environment::Union, Level::Field);
+
+const ANY: &[Target] = &[
+    STRUCT,
+    STRUCT_FIELD,
+    ENUM,
+    VARIANT,
+    VARIANT_FIELD,
+    UNION,
+    UNION_MEMBER,
+];
+const TOPS: &[Target] = &[STRUCT, ENUM, UNION];
+const FIELDS: &[Target] = &[STRUCT_FIELD, VARIANT_FIELD, UNION_MEMBER];
+const TOPS_AND_FIELDS: &[Target] = &[
+    STRUCT,
+    ENUM,
+    UNION,
+    STRUCT_FIELD,
+    VARIANT_FIELD,
+    UNION_MEMBER,
+];
+// Unions (de)serialize only the selected member's value, so attributes that pad, check or skip a
+// value alongside others are limited to structs and enums
+const NON_UNION_FIELDS: &[Target] = &[STRUCT_FIELD, VARIANT_FIELD];
+const STRUCT_AND_FIELDS: &[Target] = &[STRUCT, STRUCT_FIELD, VARIANT_FIELD];
+
+// One way of writing an attribute, with an example of it and the targets it can be used on
+struct Usage {
+    form: Form,
+    example: &'static str,
+    targets: &'static [Target],
+}
+
+const fn usage(form: Form, example: &'static str, targets: &'static [Target]) -> Usage {
+    Usage {
+        form,
+        example,
+        targets,
+    }
+}
+
+// Every attribute #[binary(...)] accepts, and how each may be used
+const ATTRS: &[(&str, &[Usage])] = &[
+    ("little", &[usage(Form::Word, "little", ANY)]),
+    ("big", &[usage(Form::Word, "big", ANY)]),
+    ("reset", &[usage(Form::Word, "reset", ANY)]),
+    ("check_pad", &[usage(Form::Word, "check_pad", ANY)]),
+    ("check_encode", &[usage(Form::Word, "check_encode", ANY)]),
+    ("len", &[usage(Form::List, "len(u16, big)", ANY)]),
+    ("inner", &[usage(Form::List, "inner(len(u8), big)", ANY)]),
+    ("key", &[usage(Form::List, "key(len(u8), big)", ANY)]),
+    ("value", &[usage(Form::List, "value(len(u8), big)", ANY)]),
+    ("fill", &[usage(Form::Value, "fill = 0xff", ANY)]),
+    (
+        "nest",
+        &[usage(
+            Form::List,
+            "nest(u16)",
+            &[STRUCT, STRUCT_FIELD, ENUM, VARIANT, VARIANT_FIELD],
+        )],
+    ),
+    (
+        "flags",
+        &[
+            usage(Form::Word, "flags", NON_UNION_FIELDS),
+            usage(Form::List, "flags(0x01)", NON_UNION_FIELDS),
+        ],
+    ),
+    (
+        "tag",
+        &[
+            usage(Form::Word, "tag", &[VARIANT_FIELD]),
+            usage(Form::List, "tag(u8)", &[ENUM]),
+            usage(
+                Form::Value,
+                "tag = ...",
+                &[VARIANT, STRUCT_FIELD, VARIANT_FIELD, UNION_MEMBER],
+            ),
+        ],
+    ),
+    ("default", &[usage(Form::Word, "default", &[VARIANT])]),
+    (
+        "rest",
+        &[usage(Form::Word, "rest", &[STRUCT_FIELD, VARIANT_FIELD])],
+    ),
+    ("raw", &[usage(Form::Word, "raw", &[UNION])]),
+    (
+        "unsafe_select",
+        &[usage(Form::Value, "unsafe_select = kind", &[UNION])],
+    ),
+    (
+        "assert",
+        &[usage(
+            Form::List,
+            "assert(self.len > 0, \"message\")",
+            &[STRUCT, VARIANT, STRUCT_FIELD, VARIANT_FIELD],
+        )],
+    ),
+    (
+        "validate",
+        &[usage(
+            Form::Value,
+            "validate = path::to::fn",
+            &[STRUCT, ENUM, STRUCT_FIELD, VARIANT_FIELD],
+        )],
+    ),
+    (
+        "bound",
+        &[
+            usage(Form::Value, "bound = \"T: Trait\"", TOPS_AND_FIELDS),
+            usage(
+                Form::List,
+                "bound(serialize = \"...\", deserialize = \"...\")",
+                TOPS_AND_FIELDS,
+            ),
+        ],
+    ),
+    (
+        "crate",
+        &[usage(Form::Value, "crate = path::to::binary", TOPS)],
+    ),
+    (
+        "import",
+        &[
+            usage(Form::List, "import(version: u8)", TOPS),
+            usage(Form::Value, "import = \"name: Type\"", TOPS),
+        ],
+    ),
+    (
+        "args",
+        &[
+            usage(Form::List, "args(self.version)", FIELDS),
+            usage(Form::Value, "args = self.len", FIELDS),
+        ],
+    ),
+    (
+        "if",
+        &[usage(
+            Form::Value,
+            "if = self.version >= 2",
+            NON_UNION_FIELDS,
+        )],
+    ),
+    (
+        "pad_before",
+        &[usage(Form::Value, "pad_before = 2", STRUCT_AND_FIELDS)],
+    ),
+    (
+        "pad_after",
+        &[usage(Form::Value, "pad_after = 2", STRUCT_AND_FIELDS)],
+    ),
+    (
+        "align",
+        &[
+            usage(Form::Value, "align = 4", STRUCT_AND_FIELDS),
+            usage(Form::List, "align(stream = 4)", STRUCT_AND_FIELDS),
+        ],
+    ),
+    ("size", &[usage(Form::Value, "size = 16", TOPS)]),
+    ("map", &[usage(Form::Value, "map = path::to::fn", FIELDS)]),
+    (
+        "try_map",
+        &[usage(Form::Value, "try_map = path::to::fn", FIELDS)],
+    ),
+    ("from", &[usage(Form::Value, "from = Type", FIELDS)]),
+    ("try_from", &[usage(Form::Value, "try_from = Type", FIELDS)]),
+    ("into", &[usage(Form::Value, "into = Type", FIELDS)]),
+    ("try_into", &[usage(Form::Value, "try_into = Type", FIELDS)]),
+    (
+        "with",
+        &[usage(Form::Value, "with = path::to::module", FIELDS)],
+    ),
+    (
+        "serialize_with",
+        &[usage(Form::Value, "serialize_with = path::to::fn", FIELDS)],
+    ),
+    (
+        "deserialize_with",
+        &[usage(
+            Form::Value,
+            "deserialize_with = path::to::fn",
+            FIELDS,
+        )],
+    ),
+];
+
+fn describe(target: Target) -> &'static str {
+    match target {
+        STRUCT => "a struct",
+        STRUCT_FIELD => "a struct field",
+        ENUM => "an enum",
+        VARIANT => "an enum variant",
+        VARIANT_FIELD => "an enum variant's field",
+        UNION => "a union",
+        _ => "a union member",
+    }
+}
+
+fn describe_all(targets: &[Target]) -> String {
+    if targets.len() == ANY.len() {
+        return "anything".to_string();
+    }
+    let described: Vec<_> = targets.iter().map(|t| describe(*t)).collect();
+    join_or(&described)
+}
+
+// Joins items as "a", "a or b", or "a, b or c"
+fn join_or(items: &[&str]) -> String {
+    match items {
+        [] => String::new(),
+        [one] => one.to_string(),
+        [rest @ .., last] => format!("{} or {}", rest.join(", "), last),
+    }
+}
+
+fn quoted(items: &[&str]) -> Vec<String> {
+    items.iter().map(|i| format!("`{}`", i)).collect()
+}
+
+fn error(span: Span, message: String) -> TokenStream2 {
+    quote_spanned! {span=>
+        compile_error!(#message);
+    }
+}
+
+// The number of single character edits turning `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let next = (row[j + 1] + 1)
+                .min(row[j] + 1)
+                .min(diagonal + usize::from(ca != *cb));
+            diagonal = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+// The option closest to a misspelt `name`, if any is close enough to be what was meant
+fn closest<'a>(name: &str, options: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    options
+        .into_iter()
+        .map(|o| (edit_distance(name, o), o))
+        .filter(|(d, o)| *d <= 2 && *d < o.len().max(name.len()) / 2 + 1)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, o)| o)
+}
+
+fn suggestion(name: &str, options: &[&str]) -> String {
+    match closest(name, options.iter().copied()) {
+        Some(o) => format!(" (did you mean `{}`?)", o),
+        None => String::new(),
+    }
+}
+
+// Checks that an attribute exists, and is written in a form and on a target it allows
+pub(crate) fn check_attr(meta: &Meta, context: Target) -> Option<TokenStream2> {
+    let name = meta.name().to_string();
+    let span = meta.name().span();
+    let form = Form::of(meta);
+    let usages = match ATTRS.iter().find(|(n, _)| *n == name) {
+        Some((_, usages)) => usages,
+        None => return Some(unknown_attr(span, &name, context)),
+    };
+    let usage = match usages.iter().find(|u| u.form == form) {
+        Some(u) => u,
+        None => {
+            let examples: Vec<_> = usages.iter().map(|u| u.example).collect();
+            let written = match form {
+                Form::Word => name.clone(),
+                Form::List => format!("{}(...)", name),
+                Form::Value => format!("{} = ...", name),
+            };
+            return Some(bad_form(meta.span(), &written, &examples));
+        }
+    };
+    if usage.targets.contains(&context) {
+        return None;
+    }
+    let elsewhere: Vec<_> = usages
+        .iter()
+        .filter(|u| u.targets.contains(&context))
+        .map(|u| format!("`{}`", u.example))
+        .collect();
+    let hint = if elsewhere.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "; on {}, write {}",
+            describe(context),
+            join_or(&elsewhere.iter().map(|s| s.as_str()).collect::<Vec<_>>())
+        )
+    };
+    Some(error(
+        span,
+        format!(
+            "`{}` cannot be used on {}; it can be used on {}{}",
+            usage.example,
+            describe(context),
+            describe_all(usage.targets),
+            hint
+        ),
+    ))
+}
+
+// Reports an attribute that does not exist, listing those that can be used where it was found
+pub(crate) fn unknown_attr(span: Span, name: &str, context: Target) -> TokenStream2 {
+    let valid: Vec<&str> = ATTRS
+        .iter()
+        .filter(|(_, usages)| usages.iter().any(|u| u.targets.contains(&context)))
+        .map(|(n, _)| *n)
+        .collect();
+    let all: Vec<&str> = ATTRS.iter().map(|(n, _)| *n).collect();
+    error(
+        span,
+        format!(
+            "unknown attribute `{}`{}; attributes that can be used on {} are: {}",
+            name,
+            suggestion(name, &all),
+            describe(context),
+            valid.join(", ")
+        ),
+    )
+}
+
+// Reports an argument `arg` of attribute `attr` that is not one of `options`
+pub(crate) fn unknown_arg(span: Span, attr: &str, arg: &str, options: &[&str]) -> TokenStream2 {
+    error(
+        span,
+        format!(
+            "unknown argument `{}` to `{}`{}; expected {}",
+            arg,
+            attr,
+            suggestion(arg, options),
+            join_or(
+                &quoted(options)
+                    .iter()
+                    .map(|s| s.as_str())
+                    .collect::<Vec<_>>()
+            )
+        ),
+    )
+}
+
+fn bad_form(span: Span, written: &str, examples: &[&str]) -> TokenStream2 {
+    error(
+        span,
+        format!(
+            "`{}` is not a valid form of this attribute; expected {}",
+            written,
+            join_or(
+                &quoted(examples)
+                    .iter()
+                    .map(|s| s.as_str())
+                    .collect::<Vec<_>>()
+            )
+        ),
+    )
+}
+
+// Reports arguments of attribute `attr` written in the wrong form
+pub(crate) fn bad_args(span: Span, attr: &str, examples: &[&str]) -> TokenStream2 {
+    error(
+        span,
+        format!(
+            "invalid arguments to `{}`; expected {}",
+            attr,
+            join_or(
+                &quoted(examples)
+                    .iter()
+                    .map(|s| s.as_str())
+                    .collect::<Vec<_>>()
+            )
+        ),
+    )
+}
+
+// The arguments each attribute taking a list accepts, and examples of how it is written
+pub(crate) const INT_ARGS: &[&str] = &["u8", "u16", "u32", "u64", "i8", "i16", "i32", "i64"];
+pub(crate) const LEN_ARGS: &[&str] = &[
+    "u8", "u16", "u32", "u64", "i8", "i16", "i32", "i64", "none", "little", "big",
+];
+pub(crate) const LEN_USAGE: &[&str] = &["len(u16)", "len(u16, big)", "len(none)"];
+pub(crate) const TAG_ARGS: &[&str] = &[
+    "u8", "u16", "u32", "u64", "i8", "i16", "i32", "i64", "little", "big",
+];
+pub(crate) const TAG_USAGE: &[&str] = &["tag(u8)", "tag(u16, big)"];
+pub(crate) const NEST_USAGE: &[&str] = &["nest(u16)", "nest(u16, big)"];
+pub(crate) const ALIGN_USAGE: &[&str] = &["align(stream = 4)"];
+pub(crate) const INNER_ARGS: &[&str] = &["little", "big", "len", "inner", "key", "value"];
+pub(crate) const INNER_USAGE: &[&str] = &["inner(len(u8), big)", "key(inner(little))"];
+pub(crate) const BOUND_ARGS: &[&str] = &["serialize", "deserialize"];
+pub(crate) const BOUND_USAGE: &[&str] = &["bound(serialize = \"...\", deserialize = \"...\")"];
+pub(crate) const ASSERT_USAGE: &[&str] =
+    &["assert(self.len > 0)", "assert(self.len > 0, \"message\")"];
+pub(crate) const FLAGS_ARGS: &[&str] = &["strict", "mask", "eq", "word"];
+pub(crate) const FLAGS_USAGE: &[&str] = &[
+    "flags(strict)",
+    "flags(0x01)",
+    "flags(mask = 0x06, eq = 0x02)",
+    "flags(0x01, word = \"name\")",
+];
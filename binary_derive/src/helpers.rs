@@ -1,8 +1,17 @@
+use std::convert::TryFrom;
+
+use proc_macro2::{Delimiter, Group, Spacing, TokenTree};
 use syn::export::TokenStream2;
+use syn::parse::{Parse, ParseStream, Parser};
+use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
-use syn::{Attribute, Expr, Ident, IntSuffix, Lit, Meta, NestedMeta, Type, Variant};
+use syn::{
+    Attribute, BinOp, Expr, ExprBinary, ExprLit, ExprUnary, Ident, IntSuffix, Lit, LitByteStr,
+    LitStr, Meta, MetaList, NestedMeta, Path, Type, UnOp, Variant, WherePredicate,
+};
 
 use crate::context::{Environment, Level};
+use crate::diagnostics;
 use crate::SelfAttrs;
 
 #[derive(Copy, Clone)]
@@ -18,7 +27,16 @@ pub(crate) enum SizeType {
 }
 
 impl SizeType {
-    fn to_type_suffix(&self) -> (Type, IntSuffix) {
+    // The number of bytes a length of this type is encoded as
+    pub(crate) fn size(self) -> usize {
+        match self {
+            Self::U8 | Self::I8 => 1,
+            Self::U16 | Self::I16 => 2,
+            Self::U32 | Self::I32 => 4,
+            Self::U64 | Self::I64 => 8,
+        }
+    }
+    fn to_type_suffix(self) -> (Type, IntSuffix) {
         match self {
             Self::U8 => (parse_quote! {u8}, IntSuffix::U8),
             Self::U16 => (parse_quote! {u16}, IntSuffix::U16),
@@ -32,14 +50,14 @@ impl SizeType {
     }
     fn build_attr_form(&self) -> TokenStream2 {
         match self {
-            SizeType::U8 => quote! {::binary::attr::Len::U8},
-            SizeType::U16 => quote! {::binary::attr::Len::U16},
-            SizeType::U32 => quote! {::binary::attr::Len::U32},
-            SizeType::U64 => quote! {::binary::attr::Len::U64},
-            SizeType::I8 => quote! {::binary::attr::Len::I8},
-            SizeType::I16 => quote! {::binary::attr::Len::I16},
-            SizeType::I32 => quote! {::binary::attr::Len::I32},
-            SizeType::I64 => quote! {::binary::attr::Len::I64},
+            SizeType::U8 => quote! {_binary::attr::Len::U8},
+            SizeType::U16 => quote! {_binary::attr::Len::U16},
+            SizeType::U32 => quote! {_binary::attr::Len::U32},
+            SizeType::U64 => quote! {_binary::attr::Len::U64},
+            SizeType::I8 => quote! {_binary::attr::Len::I8},
+            SizeType::I16 => quote! {_binary::attr::Len::I16},
+            SizeType::I32 => quote! {_binary::attr::Len::I32},
+            SizeType::I64 => quote! {_binary::attr::Len::I64},
         }
     }
 }
@@ -59,26 +77,126 @@ impl SizeTypeExt for Option<SizeType> {
     }
 }
 
+// How the fields of the value being (de)serialized can be reached from generated code
+#[derive(Copy, Clone)]
+pub(crate) enum FieldAccess {
+    Owned,    // decoded fields, held by value in `self_<name>` locals
+    Borrowed, // fields of an enum variant being encoded, bound by reference to `self_<name>`
+    SelfRef,  // fields of a struct being encoded, reachable through `self`
+}
+
+// Rewrites `self.<field>` in a user-supplied expression so that it refers to the field as the
+// generated code holds it
+pub(crate) fn rewrite_field_refs(expr: TokenStream2, access: FieldAccess) -> TokenStream2 {
+    if let FieldAccess::SelfRef = access {
+        return expr;
+    }
+
+    let mut out = vec![];
+    let mut tokens = expr.into_iter();
+    while let Some(tt) = tokens.next() {
+        match tt {
+            TokenTree::Ident(ref i) if i == "self" => {
+                let mut lookahead = tokens.clone();
+                let dot =
+                    matches!(lookahead.next(), Some(TokenTree::Punct(ref p)) if p.as_char() == '.');
+                let field = match lookahead.next() {
+                    Some(TokenTree::Ident(f)) => Some(f.to_string()),
+                    Some(TokenTree::Literal(l)) => Some(l.to_string()),
+                    _ => None,
+                };
+                match (dot, field) {
+                    (true, Some(field)) => {
+                        tokens = lookahead;
+                        let local = Ident::new(&format!("self_{}", field), i.span());
+                        out.push(match access {
+                            FieldAccess::Borrowed => quote! { (*#local) },
+                            _ => quote! { #local },
+                        });
+                    }
+                    _ => out.push(quote! { #tt }),
+                }
+            }
+            TokenTree::Group(g) => {
+                let mut group = Group::new(g.delimiter(), rewrite_field_refs(g.stream(), access));
+                group.set_span(g.span());
+                out.push(quote! { #group });
+            }
+            tt => out.push(quote! { #tt }),
+        }
+    }
+    quote! { #(#out)* }
+}
+
+// Lets #[binary(...)] take Rust expressions as values, as in `if = self.len > 0`, by quoting any
+// `key = value` whose value is not a single literal, and any other expression in a list, at any
+// depth of nesting, so that the attribute parses as a Meta. Values may still be given as string
+// literals.
+fn quote_expr_values(attr: &Attribute) -> Attribute {
+    fn quote_list(tokens: TokenStream2) -> TokenStream2 {
+        let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+        let mut out = vec![];
+        for item in tokens.split(|tt| matches!(tt, TokenTree::Punct(p) if p.as_char() == ',')) {
+            match item {
+                [TokenTree::Ident(key), TokenTree::Punct(eq), value @ ..]
+                    if eq.as_char() == '='
+                        && eq.spacing() == Spacing::Alone
+                        && !matches!(value, [TokenTree::Literal(_)]) =>
+                {
+                    let value: TokenStream2 = value.iter().cloned().collect();
+                    let value = LitStr::new(&value.to_string(), value.span());
+                    out.push(quote! { #key #eq #value });
+                }
+                [TokenTree::Ident(key), TokenTree::Group(g)]
+                    if g.delimiter() == Delimiter::Parenthesis =>
+                {
+                    let mut group = Group::new(Delimiter::Parenthesis, quote_list(g.stream()));
+                    group.set_span(g.span());
+                    out.push(quote! { #key #group });
+                }
+                [] | [TokenTree::Ident(_)] | [TokenTree::Literal(_)] => {
+                    out.push(item.iter().cloned().collect())
+                }
+                [TokenTree::Ident(_), TokenTree::Punct(eq), TokenTree::Literal(_)]
+                    if eq.as_char() == '=' =>
+                {
+                    out.push(item.iter().cloned().collect())
+                }
+                // An expression standing on its own in a list, as in assert(self.len > 0, "...")
+                _ => {
+                    let value: TokenStream2 = item.iter().cloned().collect();
+                    let value = LitStr::new(&value.to_string(), value.span());
+                    out.push(quote! { #value });
+                }
+            }
+        }
+        quote! { #(#out),* }
+    }
+
+    let mut attr = attr.clone();
+    if attr.path.is_ident("binary") {
+        let tts: Vec<TokenTree> = attr.tts.clone().into_iter().collect();
+        if let [TokenTree::Group(g)] = tts.as_slice() {
+            if g.delimiter() == Delimiter::Parenthesis {
+                let mut group = Group::new(Delimiter::Parenthesis, quote_list(g.stream()));
+                group.set_span(g.span());
+                attr.tts = quote! { #group };
+            }
+        }
+    }
+    attr
+}
+
 pub(crate) fn parse_attrs(
     input: &[Attribute],
     context: (Environment, Level),
 ) -> (TokenStream2, SelfAttrs, TokenStream2) {
     let mut attrs = vec![];
-    let mut self_attrs = SelfAttrs {
-        tag_ty: None,
-        tag_le: None,
-        tag_default: false,
-        nest: false,
-        nest_variants: false,
-        nest_ty: None,
-        nest_le: None,
-        flags: false,
-        flag_value: None,
-    };
+    let mut self_attrs = SelfAttrs::default();
     let mut errors = vec![];
 
     for attr in input {
-        let data = match attr.parse_meta() {
+        let data = match quote_expr_values(attr).parse_meta() {
             Ok(v) => v,
             Err(e) => {
                 errors.push(e.to_compile_error());
@@ -91,13 +209,20 @@ pub(crate) fn parse_attrs(
                 if w == "binary" {
                     let span = data.span();
                     errors.push(quote_spanned! {span=>
-                        compile_error!("illegal attribute form");
+                        compile_error!("#[binary] needs a list of attributes, such as #[binary(big)]");
                     });
                 }
                 continue;
             }
             Meta::List(l) => {
                 if l.ident == "repr" {
+                    if context == (Environment::Union, Level::Top) {
+                        self_attrs.repr_c |= l.nested.iter().any(|elem| match elem {
+                            NestedMeta::Meta(Meta::Word(w)) => w == "C" || w == "transparent",
+                            _ => false,
+                        });
+                        continue;
+                    }
                     if context != (Environment::Enum, Level::Top) {
                         continue; // ignore, this isn't our attr to complain about
                     }
@@ -108,9 +233,12 @@ pub(crate) fn parse_attrs(
                                 match parse_size_attr_arg(w) {
                                     Ok(Some(v)) => self_attrs.tag_ty = Some(v.to_type_suffix()),
                                     Ok(None) => continue,
-                                    Err(None) => errors.push(quote_spanned! {span=>
-                                        compile_error!("unknown attribute");
-                                    }),
+                                    Err(None) => errors.push(diagnostics::unknown_arg(
+                                        span,
+                                        "repr",
+                                        &w.to_string(),
+                                        diagnostics::INT_ARGS,
+                                    )),
                                     Err(Some(v)) => errors.push(v),
                                 }
                             }
@@ -119,6 +247,12 @@ pub(crate) fn parse_attrs(
                     }
                 } else if l.ident == "binary" {
                     for elem in &l.nested {
+                        if let NestedMeta::Meta(meta) = elem {
+                            if let Some(e) = diagnostics::check_attr(meta, context) {
+                                errors.push(e);
+                                continue;
+                            }
+                        }
                         match elem {
                             NestedMeta::Meta(meta) => match &meta {
                                 Meta::Word(word) => {
@@ -126,46 +260,25 @@ pub(crate) fn parse_attrs(
                                     let s = word.to_string();
                                     match s.as_str() {
                                         "little" => attrs.push(quote_spanned! {span=>
-                                            attrs.endian = ::binary::attr::Endian::Little;
+                                            attrs.endian = _binary::attr::Endian::Little;
                                         }),
                                         "big" => attrs.push(quote_spanned! {span=>
-                                            attrs.endian = ::binary::attr::Endian::Big;
-                                        }),
-                                        "reset" => attrs.push(quote_spanned! {span=>
-                                            attrs = ::binary::attr::Attrs::zero();
+                                            attrs.endian = _binary::attr::Endian::Big;
                                         }),
-                                        "nest" => {
-                                            if context != (Environment::Enum, Level::Top) {
-                                                errors.push(quote_spanned! {span=>
-                                                    compile_error!("illegal attribute target");
-                                                });
-                                            } else {
-                                                self_attrs.nest_variants = true;
-                                            }
-                                        }
-                                        "flags" => {
-                                            if context.1 != Level::Field {
-                                                errors.push(quote_spanned! {span=>
-                                                    compile_error!("illegal attribute target");
-                                                });
-                                            } else {
-                                                self_attrs.flags = true;
-                                            }
-                                        }
-                                        "default" => {
-                                            if context != (Environment::Enum, Level::Variant) {
-                                                errors.push(quote_spanned! {span=>
-                                                    compile_error!("illegal attribute target");
-                                                });
-                                            } else {
-                                                self_attrs.tag_default = true;
-                                            }
-                                        }
-                                        _ => {
-                                            errors.push(quote_spanned! {span=>
-                                                compile_error!("unknown attribute");
+                                        "reset" => {
+                                            attrs.push(quote_spanned! {span=>
+                                                attrs = _binary::attr::Attrs::zero();
                                             });
+                                            self_attrs.len = Some(None);
                                         }
+                                        "flags" => self_attrs.flags = true,
+                                        "check_pad" => self_attrs.check_pad = true,
+                                        "rest" => self_attrs.rest = true,
+                                        "raw" => self_attrs.raw = true,
+                                        "check_encode" => self_attrs.check_encode = true,
+                                        "tag" => self_attrs.tag_field = true,
+                                        "default" => self_attrs.tag_default = true,
+                                        _ => unreachable!(),
                                     }
                                 }
                                 Meta::List(list) => match list.ident.to_string().as_str() {
@@ -177,164 +290,340 @@ pub(crate) fn parse_attrs(
                                                     let s = word.to_string();
                                                     match s.as_str() {
                                                         "little" => attrs.push(quote_spanned! {span=>
-                                                            attrs.len_endian = ::binary::attr::Endian::Little;
+                                                            attrs.len_endian = _binary::attr::Endian::Little;
                                                         }),
                                                         "big" => attrs.push(quote_spanned! {span=>
-                                                            attrs.len_endian = ::binary::attr::Endian::Big;
+                                                            attrs.len_endian = _binary::attr::Endian::Big;
                                                         }),
-                                                        _ => {
-                                                            match parse_size_attr_arg(word) {
-                                                                Ok(v) => {
-                                                                    let ty = v.build_attr_form();
-                                                                    attrs.push(quote!{
-                                                                        attrs.len = #ty;
-                                                                    });
-                                                                }
-                                                                Err(None) => {
-                                                                    errors.push(quote_spanned! {span=>
-                                                                        compile_error!("unknown attribute");
-                                                                    })
-                                                                }
-                                                                Err(Some(v)) => errors.push(v),
+                                                        _ => match parse_size_attr_arg(word) {
+                                                            Ok(v) => {
+                                                                let ty = v.build_attr_form();
+                                                                attrs.push(quote! {
+                                                                    attrs.len = #ty;
+                                                                });
+                                                                self_attrs.len = Some(v);
                                                             }
-                                                        }
+                                                            Err(None) => errors.push(diagnostics::unknown_arg(
+                                                                span,
+                                                                "len",
+                                                                &s,
+                                                                diagnostics::LEN_ARGS,
+                                                            )),
+                                                            Err(Some(v)) => errors.push(v),
+                                                        },
                                                     }
                                                 }
                                                 _ => {
                                                     let span = elem.span();
-                                                    errors.push(quote_spanned! {span=>
-                                                        compile_error!("illegal attribute form");
-                                                    });
+                                                    errors.push(diagnostics::bad_args(
+                                                        span,
+                                                        "len",
+                                                        diagnostics::LEN_USAGE,
+                                                    ));
                                                 }
                                             }
                                         }
                                     }
                                     "tag" => {
-                                        if context != (Environment::Enum, Level::Top) {
-                                            let span = list.span();
-                                            errors.push(quote_spanned! {span=>
-                                                compile_error!("illegal attribute target");
-                                            });
-                                        } else {
-                                            for elem in &list.nested {
-                                                match elem {
-                                                    NestedMeta::Meta(Meta::Word(word)) => {
-                                                        let span = word.span();
-                                                        let s = word.to_string();
-                                                        match s.as_str() {
-                                                            "little" => {
-                                                                self_attrs.tag_le = Some(true)
-                                                            }
-                                                            "big" => {
-                                                                self_attrs.tag_le = Some(false)
-                                                            }
-                                                            _ => {
-                                                                match parse_size_attr_arg(word) {
-                                                                    Ok(Some(v)) => self_attrs.tag_ty = Some(v.to_type_suffix()),
-                                                                    Err(None) | Ok(None) => {
-                                                                        errors.push(quote_spanned! {span=>
-                                                                            compile_error!("unknown attribute");
-                                                                        })
-                                                                    }
-                                                                    Err(Some(v)) => errors.push(v),
-                                                                }
+                                        for elem in &list.nested {
+                                            match elem {
+                                                NestedMeta::Meta(Meta::Word(word)) => {
+                                                    let span = word.span();
+                                                    let s = word.to_string();
+                                                    match s.as_str() {
+                                                        "little" => self_attrs.tag_le = Some(true),
+                                                        "big" => self_attrs.tag_le = Some(false),
+                                                        _ => match parse_size_attr_arg(word) {
+                                                            Ok(Some(v)) => {
+                                                                self_attrs.tag_ty =
+                                                                    Some(v.to_type_suffix())
                                                             }
-                                                        }
-                                                    }
-                                                    _ => {
-                                                        let span = elem.span();
-                                                        errors.push(quote_spanned! {span=>
-                                                        compile_error!("illegal attribute form");
-                                                    });
+                                                            Err(None) | Ok(None) => errors.push(
+                                                                diagnostics::unknown_arg(
+                                                                    span,
+                                                                    "tag",
+                                                                    &s,
+                                                                    diagnostics::TAG_ARGS,
+                                                                ),
+                                                            ),
+                                                            Err(Some(v)) => errors.push(v),
+                                                        },
                                                     }
                                                 }
+                                                _ => {
+                                                    let span = elem.span();
+                                                    errors.push(diagnostics::bad_args(
+                                                        span,
+                                                        "tag",
+                                                        diagnostics::TAG_USAGE,
+                                                    ));
+                                                }
                                             }
                                         }
                                     }
+                                    "align" => match parse_align_stream(list) {
+                                        Ok(v) => {
+                                            self_attrs.align = Some(v);
+                                            self_attrs.align_stream = true;
+                                        }
+                                        Err(e) => errors.push(e),
+                                    },
                                     "nest" => {
-                                        if context != (Environment::Enum, Level::Top) {
-                                            let span = list.span();
-                                            errors.push(quote_spanned! {span=>
-                                                compile_error!("illegal attribute target");
-                                            });
-                                        } else {
-                                            self_attrs.nest_variants = true;
-                                            for elem in &list.nested {
-                                                match elem {
-                                                    NestedMeta::Meta(Meta::Word(word)) => {
-                                                        let span = word.span();
-                                                        let s = word.to_string();
-                                                        match s.as_str() {
-                                                            "little" => {
-                                                                self_attrs.nest_le = Some(true)
-                                                            }
-                                                            "big" => {
-                                                                self_attrs.nest_le = Some(false)
-                                                            }
-                                                            _ => {
-                                                                match parse_size_attr_arg(word) {
-                                                                    Ok(Some(v)) => self_attrs.nest_ty = Some(v),
-                                                                    Err(None) | Ok(None) => {
-                                                                        errors.push(quote_spanned! {span=>
-                                                                            compile_error!("unknown attribute");
-                                                                        })
-                                                                    }
-                                                                    Err(Some(v)) => errors.push(v),
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                    _ => {
-                                                        let span = elem.span();
-                                                        errors.push(quote_spanned! {span=>
-                                                            compile_error!("illegal attribute form");
-                                                        });
-                                                    }
+                                        if let Err(e) = parse_nest(list, context, &mut self_attrs) {
+                                            errors.push(e);
+                                        }
+                                    }
+                                    "bound" => {
+                                        for elem in &list.nested {
+                                            let span = elem.span();
+                                            let (which, lit) = match elem {
+                                                NestedMeta::Meta(Meta::NameValue(nv)) => {
+                                                    (nv.ident.to_string(), &nv.lit)
+                                                }
+                                                _ => {
+                                                    errors.push(diagnostics::bad_args(
+                                                        span,
+                                                        "bound",
+                                                        diagnostics::BOUND_USAGE,
+                                                    ));
+                                                    continue;
                                                 }
+                                            };
+                                            let bound = match parse_bound(lit) {
+                                                Ok(v) => Some(v),
+                                                Err(e) => {
+                                                    errors.push(e);
+                                                    continue;
+                                                }
+                                            };
+                                            match which.as_str() {
+                                                "serialize" => self_attrs.ser_bound = bound,
+                                                "deserialize" => self_attrs.de_bound = bound,
+                                                _ => errors.push(diagnostics::unknown_arg(
+                                                    span,
+                                                    "bound",
+                                                    &which,
+                                                    diagnostics::BOUND_ARGS,
+                                                )),
                                             }
                                         }
                                     }
-                                    "flags" => {
-                                        let span = list.span();
-                                        if context.1 != Level::Field {
-                                            errors.push(quote_spanned! {span=>
-                                                compile_error!("illegal attribute target");
+                                    "import" => {
+                                        let parser =
+                                            Punctuated::<Import, Token![,]>::parse_terminated;
+                                        match parser.parse2(list_tokens(list)) {
+                                            Ok(v) => self_attrs
+                                                .imports
+                                                .extend(v.into_iter().map(|i| (i.name, i.ty))),
+                                            Err(e) => errors.push(e.to_compile_error()),
+                                        }
+                                    }
+                                    "args" => self_attrs.args = Some(list_tokens(list)),
+                                    "inner" | "key" | "value" => match parse_patch(list) {
+                                        Ok(patch) => {
+                                            let level = &list.ident;
+                                            attrs.push(quote! {
+                                                attrs.#level = Some(#patch);
                                             });
-                                        } else if list.nested.len() != 1 {
+                                        }
+                                        Err(e) => errors.push(e),
+                                    },
+                                    "assert" => {
+                                        let span = list.span();
+                                        let mut args = list.nested.iter();
+                                        let expr = match args.next() {
+                                            Some(NestedMeta::Literal(Lit::Str(s))) => s,
+                                            _ => {
+                                                errors.push(diagnostics::bad_args(
+                                                    span,
+                                                    "assert",
+                                                    diagnostics::ASSERT_USAGE,
+                                                ));
+                                                continue;
+                                            }
+                                        };
+                                        let message = match args.next() {
+                                            None => {
+                                                format!("assertion failed: {}", expr.value())
+                                            }
+                                            Some(NestedMeta::Literal(Lit::Str(s)))
+                                                if args.next().is_none() =>
+                                            {
+                                                s.value()
+                                            }
+                                            Some(_) => {
+                                                errors.push(diagnostics::bad_args(
+                                                    span,
+                                                    "assert",
+                                                    diagnostics::ASSERT_USAGE,
+                                                ));
+                                                continue;
+                                            }
+                                        };
+                                        match expr.parse() {
+                                            Ok(expr) => self_attrs.asserts.push((expr, message)),
+                                            Err(e) => errors.push(e.to_compile_error()),
+                                        }
+                                    }
+                                    "flags" => {
+                                        if let Err(e) = parse_flags(list, &mut self_attrs) {
+                                            errors.push(e);
+                                        }
+                                    }
+                                    _ => unreachable!(),
+                                },
+                                Meta::NameValue(nv) => match nv.ident.to_string().as_str() {
+                                    "if" => {
+                                        let span = nv.span();
+                                        if self_attrs.flag.is_some() {
                                             errors.push(quote_spanned! {span=>
-                                                compile_error!("illegal attribute argument");
-                                            });
+                                                    compile_error!("#[binary(if = ...)] cannot be combined with #[binary(flags(...))]");
+                                                });
                                         } else {
-                                            match &list.nested[0] {
-                                                NestedMeta::Literal(Lit::Int(i)) => {
-                                                    self_attrs.flag_value = Some(i.value());
-                                                }
-                                                _ => {
-                                                    errors.push(quote_spanned! {span=>
-                                                        compile_error!("illegal attribute argument");
-                                                    });
-                                                }
+                                            match parse_lit_str(&nv.lit) {
+                                                Ok(v) => self_attrs.condition = Some(v),
+                                                Err(e) => errors.push(e),
                                             }
                                         }
                                     }
-                                    _ => {
-                                        let span = meta.span();
-                                        errors.push(quote_spanned! {span=>
-                                            compile_error!("illegal attribute form");
-                                        });
+                                    "size" => match parse_lit_int(&nv.lit) {
+                                        Ok(v) => self_attrs.size = Some((v, nv.span())),
+                                        Err(e) => errors.push(e),
+                                    },
+                                    "pad_before" | "pad_after" | "align" | "fill" => {
+                                        let span = nv.span();
+                                        let name = nv.ident.to_string();
+                                        let v = match parse_lit_int(&nv.lit) {
+                                            Ok(v) => v,
+                                            Err(e) => {
+                                                errors.push(e);
+                                                continue;
+                                            }
+                                        };
+                                        match name.as_str() {
+                                            "pad_before" => self_attrs.pad_before = Some(v),
+                                            "pad_after" => self_attrs.pad_after = Some(v),
+                                            "align" if v == 0 => {
+                                                errors.push(quote_spanned! {span=>
+                                                    compile_error!("alignment must be non-zero");
+                                                })
+                                            }
+                                            "align" => self_attrs.align = Some(v),
+                                            _ if v > 0xff => errors.push(quote_spanned! {span=>
+                                                compile_error!("fill must be a single byte");
+                                            }),
+                                            _ => self_attrs.fill = Some(v as u8),
+                                        }
+                                    }
+                                    "map" | "try_map" | "from" | "try_from" | "into"
+                                    | "try_into" => {
+                                        let span = nv.span();
+                                        let name = nv.ident.to_string();
+                                        let fallible = name.starts_with("try_");
+                                        let result = match name.trim_start_matches("try_") {
+                                            "map" if self_attrs.from.is_some() => {
+                                                Err(quote_spanned! {span=>
+                                                    compile_error!("#[binary(map = ...)] cannot be combined with #[binary(from = ...)]");
+                                                })
+                                            }
+                                            "from" if self_attrs.map.is_some() => {
+                                                Err(quote_spanned! {span=>
+                                                    compile_error!("#[binary(from = ...)] cannot be combined with #[binary(map = ...)]");
+                                                })
+                                            }
+                                            "map" => parse_lit_str(&nv.lit)
+                                                .map(|v| self_attrs.map = Some((v, fallible))),
+                                            "from" => parse_lit_str(&nv.lit)
+                                                .map(|v| self_attrs.from = Some((v, fallible))),
+                                            _ => parse_lit_str(&nv.lit)
+                                                .map(|v| self_attrs.into = Some((v, fallible))),
+                                        };
+                                        if let Err(e) = result {
+                                            errors.push(e);
+                                        }
+                                    }
+                                    "import" => {
+                                        let span = nv.span();
+                                        let parser =
+                                            Punctuated::<Import, Token![,]>::parse_terminated;
+                                        match &nv.lit {
+                                            Lit::Str(s) => match s.parse_with(parser) {
+                                                Ok(v) => self_attrs
+                                                    .imports
+                                                    .extend(v.into_iter().map(|i| (i.name, i.ty))),
+                                                Err(e) => errors.push(e.to_compile_error()),
+                                            },
+                                            _ => errors.push(quote_spanned! {span=>
+                                                compile_error!("expected a string literal");
+                                            }),
+                                        }
                                     }
+                                    "args" => match parse_lit_str(&nv.lit) {
+                                        Ok(v) => self_attrs.args = Some(v),
+                                        Err(e) => errors.push(e),
+                                    },
+                                    "unsafe_select" => match parse_lit_str(&nv.lit) {
+                                        Ok(v) => self_attrs.select = Some(v),
+                                        Err(e) => errors.push(e),
+                                    },
+                                    "tag" => {
+                                        if context == (Environment::Union, Level::Field) {
+                                            self_attrs.tag_value = Some(nv.lit.clone());
+                                        } else if context == (Environment::Enum, Level::Variant) {
+                                            match parse_variant_tags(&nv.lit) {
+                                                Ok(v) => self_attrs.tags = v,
+                                                Err(e) => errors.push(e),
+                                            }
+                                        } else {
+                                            match parse_lit_str(&nv.lit) {
+                                                Ok(v) => self_attrs.tag_expr = Some(v),
+                                                Err(e) => errors.push(e),
+                                            }
+                                        }
+                                    }
+                                    "crate" => match parse_lit_str(&nv.lit) {
+                                        Ok(v) => self_attrs.krate = Some(v),
+                                        Err(e) => errors.push(e),
+                                    },
+                                    "bound" => match parse_bound(&nv.lit) {
+                                        Ok(v) => {
+                                            self_attrs.ser_bound = Some(v.clone());
+                                            self_attrs.de_bound = Some(v);
+                                        }
+                                        Err(e) => errors.push(e),
+                                    },
+                                    "validate" => match parse_lit_str(&nv.lit) {
+                                        Ok(v) => self_attrs.validate = Some(v),
+                                        Err(e) => errors.push(e),
+                                    },
+                                    "with" | "serialize_with" | "deserialize_with" => {
+                                        let path: Path = match parse_lit_str(&nv.lit) {
+                                            Ok(v) => v,
+                                            Err(e) => {
+                                                errors.push(e);
+                                                continue;
+                                            }
+                                        };
+                                        match nv.ident.to_string().as_str() {
+                                            "with" => {
+                                                self_attrs.serialize_with =
+                                                    Some(parse_quote! { #path::encode });
+                                                self_attrs.deserialize_with =
+                                                    Some(parse_quote! { #path::decode });
+                                            }
+                                            "serialize_with" => {
+                                                self_attrs.serialize_with = Some(path)
+                                            }
+                                            _ => self_attrs.deserialize_with = Some(path),
+                                        }
+                                    }
+                                    _ => unreachable!(),
                                 },
-                                _ => {
-                                    let span = meta.span();
-                                    errors.push(quote_spanned! {span=>
-                                        compile_error!("illegal attribute form");
-                                    });
-                                }
                             },
                             _ => {
                                 let span = elem.span();
                                 errors.push(quote_spanned! {span=>
-                                    compile_error!("illegal attribute form");
+                                    compile_error!("expected an attribute, such as `big` or `len(u16)`, found a literal");
                                 });
                             }
                         }
@@ -345,7 +634,7 @@ pub(crate) fn parse_attrs(
                 if nv.ident == "binary" {
                     let span = data.span();
                     errors.push(quote_spanned! {span=>
-                        compile_error!("illegal attribute form");
+                        compile_error!("#[binary = ...] is not supported; write a list of attributes, such as #[binary(big)]");
                     });
                 }
                 continue;
@@ -364,6 +653,368 @@ pub(crate) fn parse_attrs(
     )
 }
 
+// The test a flags(...) field applies to its flags word to decide whether it is present
+#[derive(Clone)]
+pub(crate) struct FlagTest {
+    pub(crate) mask: u64,
+    pub(crate) eq: Option<u64>, // None if any bit of the mask being set is enough
+    pub(crate) word: Option<String>,
+}
+
+// Parses inner(...), key(...) or value(...) into a reference to a const Patch, holding the
+// attributes they set for that level of a nested container
+fn parse_patch(list: &MetaList) -> Result<TokenStream2, TokenStream2> {
+    let name = list.ident.to_string();
+    let mut sets = vec![];
+    for elem in &list.nested {
+        let span = elem.span();
+        match elem {
+            NestedMeta::Meta(Meta::Word(w)) if w == "little" => sets.push(quote! {
+                patch.endian = Some(_binary::attr::Endian::Little);
+            }),
+            NestedMeta::Meta(Meta::Word(w)) if w == "big" => sets.push(quote! {
+                patch.endian = Some(_binary::attr::Endian::Big);
+            }),
+            NestedMeta::Meta(Meta::List(l)) if l.ident == "len" => {
+                for elem in &l.nested {
+                    let span = elem.span();
+                    let word = match elem {
+                        NestedMeta::Meta(Meta::Word(word)) => word,
+                        _ => {
+                            return Err(diagnostics::bad_args(span, "len", diagnostics::LEN_USAGE))
+                        }
+                    };
+                    match word.to_string().as_str() {
+                        "little" => sets.push(quote! {
+                            patch.len_endian = Some(_binary::attr::Endian::Little);
+                        }),
+                        "big" => sets.push(quote! {
+                            patch.len_endian = Some(_binary::attr::Endian::Big);
+                        }),
+                        _ => match parse_size_attr_arg(word) {
+                            Ok(v) => {
+                                let ty = v.build_attr_form();
+                                sets.push(quote! {
+                                    patch.len = Some(#ty);
+                                });
+                            }
+                            Err(None) => {
+                                return Err(diagnostics::unknown_arg(
+                                    span,
+                                    "len",
+                                    &word.to_string(),
+                                    diagnostics::LEN_ARGS,
+                                ))
+                            }
+                            Err(Some(v)) => return Err(v),
+                        },
+                    }
+                }
+            }
+            NestedMeta::Meta(Meta::List(l))
+                if l.ident == "inner" || l.ident == "key" || l.ident == "value" =>
+            {
+                let level = &l.ident;
+                let nested = parse_patch(l)?;
+                sets.push(quote! {
+                    patch.#level = Some(#nested);
+                });
+            }
+            NestedMeta::Meta(meta) => {
+                return Err(diagnostics::unknown_arg(
+                    span,
+                    &name,
+                    &meta.name().to_string(),
+                    diagnostics::INNER_ARGS,
+                ))
+            }
+            _ => return Err(diagnostics::bad_args(span, &name, diagnostics::INNER_USAGE)),
+        }
+    }
+    Ok(quote! {
+        {
+            const PATCH: _binary::attr::Patch = {
+                #[allow(unused_mut)]
+                let mut patch = _binary::attr::Patch::NONE;
+                #(#sets)*
+                patch
+            };
+            &PATCH
+        }
+    })
+}
+
+// Parses align(stream = N): alignment to a multiple of N bytes counted from the start of the
+// stream, rather than from the start of the enclosing structure
+fn parse_align_stream(list: &MetaList) -> Result<u64, TokenStream2> {
+    let nv = match list.nested.iter().collect::<Vec<_>>().as_slice() {
+        [NestedMeta::Meta(Meta::NameValue(nv))] if nv.ident == "stream" => nv,
+        _ => {
+            let span = list.span();
+            return Err(diagnostics::bad_args(
+                span,
+                "align",
+                diagnostics::ALIGN_USAGE,
+            ));
+        }
+    };
+    match parse_lit_int(&nv.lit)? {
+        0 => {
+            let span = nv.span();
+            Err(quote_spanned! {span=>
+                compile_error!("alignment must be non-zero");
+            })
+        }
+        v => Ok(v),
+    }
+}
+
+// Parses nest(...): the length type and byte order of the length-prefixed buffer a struct, field
+// or enum variant is (de)serialized in, or that of each variant on an enum. An enum variant may
+// opt out of its enum's with nest(none).
+fn parse_nest(
+    list: &MetaList,
+    context: (Environment, Level),
+    self_attrs: &mut SelfAttrs,
+) -> Result<(), TokenStream2> {
+    let span = list.span();
+    let variant = context == (Environment::Enum, Level::Variant);
+    for elem in &list.nested {
+        let word = match elem {
+            NestedMeta::Meta(Meta::Word(word)) => word,
+            _ => {
+                let span = elem.span();
+                return Err(diagnostics::bad_args(span, "nest", diagnostics::NEST_USAGE));
+            }
+        };
+        let span = word.span();
+        match word.to_string().as_str() {
+            "little" => self_attrs.nest_le = Some(true),
+            "big" => self_attrs.nest_le = Some(false),
+            _ => match parse_size_attr_arg(word) {
+                Ok(Some(v)) => self_attrs.nest_ty = Some(v),
+                Ok(None) if variant && list.nested.len() == 1 => {
+                    self_attrs.nest = false;
+                    self_attrs.nest_off = true;
+                    return Ok(());
+                }
+                Err(None) | Ok(None) => {
+                    let options = if variant {
+                        diagnostics::LEN_ARGS
+                    } else {
+                        diagnostics::TAG_ARGS
+                    };
+                    return Err(diagnostics::unknown_arg(
+                        span,
+                        "nest",
+                        &word.to_string(),
+                        options,
+                    ));
+                }
+                Err(Some(v)) => return Err(v),
+            },
+        }
+    }
+    if self_attrs.nest_ty.is_none() {
+        return Err(quote_spanned! {span=>
+            compile_error!("nest needs a length type, such as nest(u16)");
+        });
+    }
+    if context == (Environment::Enum, Level::Top) {
+        self_attrs.nest_variants = true;
+    } else {
+        self_attrs.nest = true;
+    }
+    Ok(())
+}
+
+// Parses the arguments of flags(...): either `strict` on a flags word, or the test of a field
+// depending on one, which is a mask or `mask = ..., eq = ...` with an optional `word = "..."`
+fn parse_flags(list: &MetaList, self_attrs: &mut SelfAttrs) -> Result<(), TokenStream2> {
+    let span = list.span();
+    let illegal = diagnostics::bad_args(span, "flags", diagnostics::FLAGS_USAGE);
+    let mut strict = false;
+    let mut mask = None;
+    let mut eq = None;
+    let mut word = None;
+    for nested in &list.nested {
+        match nested {
+            NestedMeta::Meta(Meta::Word(w)) if w == "strict" => strict = true,
+            NestedMeta::Literal(Lit::Int(i)) if mask.is_none() => mask = Some(i.value()),
+            NestedMeta::Meta(Meta::NameValue(nv)) => match (nv.ident.to_string().as_str(), &nv.lit)
+            {
+                ("mask", lit) if mask.is_none() => mask = Some(parse_lit_int(lit)?),
+                ("eq", lit) if eq.is_none() => eq = Some(parse_lit_int(lit)?),
+                ("word", Lit::Str(s)) if word.is_none() => word = Some(s.value()),
+                (name, _) if !diagnostics::FLAGS_ARGS.contains(&name) => {
+                    let span = nv.ident.span();
+                    return Err(diagnostics::unknown_arg(
+                        span,
+                        "flags",
+                        name,
+                        diagnostics::FLAGS_ARGS,
+                    ));
+                }
+                _ => return Err(illegal),
+            },
+            _ => return Err(illegal),
+        }
+    }
+
+    if strict {
+        if mask.is_some() || eq.is_some() || word.is_some() {
+            return Err(illegal);
+        }
+        self_attrs.flags = true;
+        self_attrs.flags_strict = true;
+        return Ok(());
+    }
+    let mask = match mask {
+        Some(m) => m,
+        None => return Err(illegal),
+    };
+    if eq.is_some_and(|eq| eq & !mask != 0) {
+        return Err(quote_spanned! {span=>
+            compile_error!("flags(eq = ...) has bits set outside of its mask");
+        });
+    }
+    if self_attrs.condition.is_some() {
+        return Err(quote_spanned! {span=>
+            compile_error!("#[binary(flags(...))] cannot be combined with #[binary(if = ...)]");
+        });
+    }
+    self_attrs.flag = Some(FlagTest { mask, eq, word });
+    Ok(())
+}
+
+// One of the tags a variant is (de)serialized with. Integer tags are constant expressions,
+// evaluated in the generated code as the enum's tag type.
+#[derive(Clone)]
+pub(crate) enum VariantTag {
+    Int(TokenStream2),
+    Range(TokenStream2, TokenStream2), // inclusive
+    Bytes(Vec<u8>),
+}
+
+// Collects the tokens of a constant expression, up to a | or ..= outside of any brackets
+fn parse_tag_expr(input: ParseStream) -> syn::parse::Result<TokenStream2> {
+    let mut tokens = TokenStream2::new();
+    while !input.is_empty() && !input.peek(Token![|]) && !input.peek(Token![..=]) {
+        let tt: TokenTree = input.parse()?;
+        tokens.extend(Some(tt));
+    }
+    if tokens.is_empty() {
+        return Err(input.error("expected a tag"));
+    }
+    Ok(tokens)
+}
+
+impl Parse for VariantTag {
+    fn parse(input: ParseStream) -> syn::parse::Result<Self> {
+        if input.peek(LitByteStr) {
+            let b: LitByteStr = input.parse()?;
+            return Ok(VariantTag::Bytes(b.value()));
+        }
+        let lo = parse_tag_expr(input)?;
+        if input.peek(Token![..=]) {
+            input.parse::<Token![..=]>()?;
+            let hi = parse_tag_expr(input)?;
+            Ok(VariantTag::Range(lo, hi))
+        } else {
+            Ok(VariantTag::Int(lo))
+        }
+    }
+}
+
+// Parses tag = ... on a variant: an integer or byte string, or a string of them separated by |,
+// where integers may be constant expressions or inclusive ranges such as 0x10..=0x1f
+fn parse_variant_tags(lit: &Lit) -> Result<Vec<VariantTag>, TokenStream2> {
+    match lit {
+        Lit::Int(i) => Ok(vec![VariantTag::Int(quote! { #i })]),
+        Lit::ByteStr(b) => Ok(vec![VariantTag::Bytes(b.value())]),
+        Lit::Str(s) => s
+            .parse_with(Punctuated::<VariantTag, Token![|]>::parse_separated_nonempty)
+            .map(|tags| tags.into_iter().collect())
+            .map_err(|e| e.to_compile_error()),
+        _ => {
+            let span = lit.span();
+            Err(quote_spanned! {span=>
+                compile_error!("expected an integer, byte string or string literal");
+            })
+        }
+    }
+}
+
+// One `name: Type` entry of #[binary(import = "...")]
+struct Import {
+    name: Ident,
+    ty: Type,
+}
+
+impl Parse for Import {
+    fn parse(input: ParseStream) -> syn::parse::Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let ty = input.parse()?;
+        Ok(Import { name, ty })
+    }
+}
+
+// The tokens inside a list such as import(version: u8) or args(self.version, 2), which
+// quote_expr_values split at each comma and quoted piece by piece
+fn list_tokens(list: &MetaList) -> TokenStream2 {
+    let items = list.nested.iter().map(|elem| match elem {
+        NestedMeta::Literal(Lit::Str(s)) => s
+            .parse::<TokenStream2>()
+            .unwrap_or_else(|e| e.to_compile_error()),
+        _ => quote! { #elem },
+    });
+    quote! { #(#items),* }
+}
+
+// Parses the where predicates of bound = "...", which may be empty
+fn parse_bound(lit: &Lit) -> Result<Vec<WherePredicate>, TokenStream2> {
+    let span = lit.span();
+    match lit {
+        Lit::Str(s) => s
+            .parse_with(Punctuated::<WherePredicate, Token![,]>::parse_terminated)
+            .map(|bound| bound.into_iter().collect())
+            .map_err(|e| e.to_compile_error()),
+        _ => Err(quote_spanned! {span=>
+            compile_error!("expected a string literal");
+        }),
+    }
+}
+
+fn parse_lit_str<T: Parse>(lit: &Lit) -> Result<T, TokenStream2> {
+    let span = lit.span();
+    match lit {
+        Lit::Str(s) => s.parse().map_err(|e| e.to_compile_error()),
+        _ => Err(quote_spanned! {span=>
+            compile_error!("expected a string literal");
+        }),
+    }
+}
+
+// Parses an integer literal, or a constant expression of them such as `1 << 4`
+fn parse_lit_int(lit: &Lit) -> Result<u64, TokenStream2> {
+    let span = lit.span();
+    let value = match lit {
+        Lit::Int(i) => Some(i.value()),
+        Lit::Str(s) => s
+            .parse()
+            .ok()
+            .and_then(|expr| eval_tag(&expr))
+            .and_then(|v| u64::try_from(v).ok()),
+        _ => None,
+    };
+    value.ok_or_else(|| {
+        quote_spanned! {span=>
+            compile_error!("expected an integer literal or constant expression");
+        }
+    })
+}
+
 fn parse_size_attr_arg(ident: &Ident) -> Result<Option<SizeType>, Option<TokenStream2>> {
     let span = ident.span();
     match ident.to_string().as_str() {
@@ -386,39 +1037,71 @@ fn parse_size_attr_arg(ident: &Ident) -> Result<Option<SizeType>, Option<TokenSt
     }
 }
 
-pub(crate) fn find_discriminant(v: &Variant) -> Result<Option<u64>, TokenStream2> {
-    if let Some((_, expr)) = &v.discriminant {
-        // explicit discriminant
-        if let Expr::Lit(lit) = expr {
-            if let Lit::Int(i) = &lit.lit {
-                Ok(Some(i.value()))
-            } else {
-                let span = expr.span();
-                Err(quote_spanned! {span=>
-                    compile_error!("derive(Bin(De)serialize) expected a literal integer here");
-                })
+// The explicit discriminant of a variant, as the tokens of its constant expression
+pub(crate) fn find_discriminant(v: &Variant) -> Option<TokenStream2> {
+    v.discriminant.as_ref().map(|(_, expr)| quote! { #expr })
+}
+
+// Evaluates a tag's constant expression, if it is made only of integer literals, negation and
+// arithmetic or bitwise operators. Other tags, such as paths to consts, are left to the compiler.
+pub(crate) fn eval_tag(tokens: &TokenStream2) -> Option<i128> {
+    fn eval(expr: &Expr) -> Option<i128> {
+        match expr {
+            Expr::Lit(ExprLit {
+                lit: Lit::Int(i), ..
+            }) => Some(i128::from(i.value())),
+            Expr::Unary(ExprUnary {
+                op: UnOp::Neg(_),
+                expr,
+                ..
+            }) => eval(expr)?.checked_neg(),
+            Expr::Binary(ExprBinary {
+                left, op, right, ..
+            }) => {
+                let (l, r) = (eval(left)?, eval(right)?);
+                match op {
+                    BinOp::Add(_) => l.checked_add(r),
+                    BinOp::Sub(_) => l.checked_sub(r),
+                    BinOp::Mul(_) => l.checked_mul(r),
+                    BinOp::Shl(_) => l.checked_shl(u32::try_from(r).ok()?),
+                    BinOp::Shr(_) => l.checked_shr(u32::try_from(r).ok()?),
+                    BinOp::BitOr(_) => Some(l | r),
+                    BinOp::BitAnd(_) => Some(l & r),
+                    BinOp::BitXor(_) => Some(l ^ r),
+                    _ => None,
+                }
             }
-        } else {
-            let span = expr.span();
-            Err(quote_spanned! {span=>
-                compile_error!("derive(Bin(De)serialize) expected a literal here");
-            })
+            _ => None,
         }
-    } else {
-        Ok(None)
+    }
+    eval(&syn::parse2(tokens.clone()).ok()?)
+}
+
+// The values an integer of the type with this suffix can hold
+pub(crate) fn int_range(suffix: &IntSuffix) -> Option<(i128, i128)> {
+    match suffix {
+        IntSuffix::U8 => Some((0, u8::MAX.into())),
+        IntSuffix::U16 => Some((0, u16::MAX.into())),
+        IntSuffix::U32 => Some((0, u32::MAX.into())),
+        IntSuffix::U64 => Some((0, u64::MAX.into())),
+        IntSuffix::I8 => Some((i8::MIN.into(), i8::MAX.into())),
+        IntSuffix::I16 => Some((i16::MIN.into(), i16::MAX.into())),
+        IntSuffix::I32 => Some((i32::MIN.into(), i32::MAX.into())),
+        IntSuffix::I64 => Some((i64::MIN.into(), i64::MAX.into())),
+        _ => None,
     }
 }
 
 pub(crate) fn build_tag_attrs(tag_le: Option<bool>) -> TokenStream2 {
     let byteorder = if tag_le.unwrap_or(true) {
-        quote! { ::binary::attr::Endian::Little; }
+        quote! { _binary::attr::Endian::Little; }
     } else {
-        quote! { ::binary::attr::Endian::Big; }
+        quote! { _binary::attr::Endian::Big; }
     };
 
     quote! {
         {
-            let mut attrs = ::binary::attr::Attrs::zero();
+            let mut attrs = _binary::attr::Attrs::zero();
             attrs.endian = #byteorder;
             attrs
         }
@@ -427,18 +1110,89 @@ pub(crate) fn build_tag_attrs(tag_le: Option<bool>) -> TokenStream2 {
 
 pub(crate) fn build_nest_attrs(nest_le: Option<bool>, nest_ty: SizeType) -> TokenStream2 {
     let byteorder = if nest_le.unwrap_or(true) {
-        quote! { ::binary::attr::Endian::Little }
+        quote! { _binary::attr::Endian::Little }
     } else {
-        quote! { ::binary::attr::Endian::Big }
+        quote! { _binary::attr::Endian::Big }
     };
     let len = nest_ty.build_attr_form();
 
     quote! {
         {
-            let mut attrs = ::binary::attr::Attrs::zero();
+            let mut attrs = _binary::attr::Attrs::zero();
             attrs.len = Some(#len);
             attrs.len_endian = #byteorder;
             attrs
         }
     }
 }
+
+// Padding written around a field or struct, as (before, after) statements. A struct aligns its
+// end rather than its start. Alignment is relative to a `Counted` buf, which `needs_position` is
+// set to request.
+pub(crate) fn build_pad_encode(
+    self_attrs: &SelfAttrs,
+    align_at_end: bool,
+    needs_position: &mut bool,
+) -> (TokenStream2, TokenStream2) {
+    let fill = self_attrs.fill.unwrap_or(0);
+    let before = self_attrs.pad_before.map(|n| {
+        quote! { _binary::BinWrite::put_padding(buf, #n, #fill)?; }
+    });
+    let align = self_attrs.align.map(|n| {
+        let padding = if self_attrs.align_stream {
+            quote! { _binary::stream_padding_to(#n)? }
+        } else {
+            *needs_position = true;
+            quote! { buf.padding_to(#n) }
+        };
+        quote! {
+            let padding = #padding;
+            _binary::BinWrite::put_padding(buf, padding, #fill)?;
+        }
+    });
+    let after = self_attrs.pad_after.map(|n| {
+        quote! { _binary::BinWrite::put_padding(buf, #n, #fill)?; }
+    });
+    if align_at_end {
+        (quote! { #before }, quote! { #after #align })
+    } else {
+        (quote! { #before #align }, quote! { #after })
+    }
+}
+
+// Padding skipped around a field or struct, as (before, after) statements; see build_pad_encode
+pub(crate) fn build_pad_decode(
+    self_attrs: &SelfAttrs,
+    align_at_end: bool,
+    needs_position: &mut bool,
+) -> (TokenStream2, TokenStream2) {
+    let fill = if self_attrs.check_pad {
+        let fill = self_attrs.fill.unwrap_or(0);
+        quote! { Some(#fill) }
+    } else {
+        quote! { None }
+    };
+    let before = self_attrs.pad_before.map(|n| {
+        quote! { _binary::BinRead::skip_padding(buf, #n, #fill)?; }
+    });
+    let align = self_attrs.align.map(|n| {
+        let padding = if self_attrs.align_stream {
+            quote! { _binary::stream_padding_to(#n)? }
+        } else {
+            *needs_position = true;
+            quote! { buf.padding_to(#n) }
+        };
+        quote! {
+            let padding = #padding;
+            _binary::BinRead::skip_padding(buf, padding, #fill)?;
+        }
+    });
+    let after = self_attrs.pad_after.map(|n| {
+        quote! { _binary::BinRead::skip_padding(buf, #n, #fill)?; }
+    });
+    if align_at_end {
+        (quote! { #before }, quote! { #after #align })
+    } else {
+        (quote! { #before #align }, quote! { #after })
+    }
+}
@@ -5,28 +5,81 @@ extern crate quote;
 
 use proc_macro::TokenStream;
 use syn::export::TokenStream2;
+use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
 use syn::{
-    Data, DeriveInput, Fields, Generics, Ident, Index, IntSuffix, LitInt, Member, Path, Type,
-    WherePredicate,
+    Data, DataUnion, DeriveInput, Field, Fields, Generics, Ident, Index, IntSuffix, Lit,
+    LitByteStr, LitInt, Member, Path, Type, Variant, WherePredicate,
 };
 
 mod context;
 use context::{Context, Environment, Level};
+mod diagnostics;
 mod helpers;
+mod size;
+mod view;
 
+#[derive(Default)]
 struct SelfAttrs {
     tag_ty: Option<(Type, IntSuffix)>, // enum, based on repr()
     tag_le: Option<bool>,              // enum
-    tag_default: bool,                 // enum field, which must be of type (tag_ty, Vec<u8>)
+    tag_default: bool,                 // enum variant; matches unknown tags, held in its tag field
 
-    nest_variants: bool,                // enum
-    nest: bool,                         // enum variant, in enum with nest_variants true
-    nest_ty: Option<helpers::SizeType>, // enum
-    nest_le: Option<bool>,              // enum
+    nest_variants: bool,                // enum; nest each variant
+    nest: bool,     // struct, field or enum variant; in a length-prefixed buffer
+    nest_off: bool, // enum variant; opts out of its enum's nest_variants
+    nest_ty: Option<helpers::SizeType>, // enum, or with nest
+    nest_le: Option<bool>, // enum, or with nest
 
-    flags: bool,             // field
-    flag_value: Option<u64>, // field
+    flags: bool,        // field; a flags word other fields' presence depends on
+    flags_strict: bool, // field with flags; reject bits no field claims
+    flag: Option<helpers::FlagTest>, // field, which must be of type Option<T>
+
+    serialize_with: Option<Path>,   // field
+    deserialize_with: Option<Path>, // field
+
+    condition: Option<TokenStream2>, // field, which must be of type Option<T>
+    rest: bool, // last field, of type Vec<u8>; holds whatever is left of the buffer
+
+    pad_before: Option<u64>, // field, or struct
+    pad_after: Option<u64>,  // field, or struct
+    align: Option<u64>,      // field, or struct
+    align_stream: bool,      // with align; counted from the start of the stream
+    fill: Option<u8>,        // any; inherited
+    check_pad: bool,         // any; inherited
+
+    asserts: Vec<(TokenStream2, String)>, // field, struct or enum variant
+    validate: Option<Path>,               // field, struct or enum
+
+    map: Option<(Path, bool)>, // field; decode through a function, which may be fallible
+    from: Option<(Type, bool)>, // field; decode as another type, converting with (Try)From
+    into: Option<(Type, bool)>, // field; encode as another type, converting with (Try)Into
+
+    imports: Vec<(Ident, Type)>, // struct, enum or union; arguments it must be given
+    krate: Option<Path>,         // struct, enum or union; path to the runtime crate
+
+    ser_bound: Option<Vec<WherePredicate>>, // top or field; replaces the inferred BinSerialize bounds
+    de_bound: Option<Vec<WherePredicate>>,  // top or field; as ser_bound, for BinDeserialize
+    args: Option<TokenStream2>,             // field; arguments to pass to its type's imports
+
+    raw: bool,                      // union; (de)serialized as its bytes in memory
+    repr_c: bool,                   // union; repr(C) or repr(transparent), so members start at 0
+    select: Option<TokenStream2>,   // union; expression selecting the active member by its tag
+    tag_value: Option<Lit>,         // union member
+    tags: Vec<helpers::VariantTag>, // enum variant; takes precedence over its discriminant
+    tag_field: bool,                // enum variant field; holds the variant's tag
+    tag_expr: Option<TokenStream2>, // field of an enum type; its tag, which is not (de)serialized
+    check_encode: bool,             // any; inherited
+
+    len: Option<Option<helpers::SizeType>>, // any; inherited; the length set by len(...) or reset
+    size: Option<(u64, proc_macro2::Span)>, // struct, enum or union; its fixed size, checked by BinSize
+}
+
+impl SelfAttrs {
+    // Whether the field is an Option<T> whose presence is decided by a flag or condition
+    fn is_optional(&self) -> bool {
+        self.flag.is_some() || self.condition.is_some()
+    }
 }
 
 #[proc_macro_derive(BinSerialize, attributes(binary))]
@@ -36,19 +89,67 @@ pub fn derive_binserialize(input: TokenStream) -> TokenStream {
     println!("derive(BinSerialize) for {}", input.ident);
     let (context, attr_errors) = Context::from_input(&input);
     let ident = &input.ident;
-    let (generics, fields) = encode_type(context, input.generics, input.data, &input.ident);
+    let imports = context.self_attrs.imports.clone();
+    let krate = context.self_attrs.krate.clone();
+    let tag_attrs = helpers::build_tag_attrs(context.self_attrs.tag_le);
+    let tagged = enum_tag_type(&context, &input.data, ident)
+        .map(|ty| (ty, build_enum_tag_of(&context, &input.data, ident)));
+    let bound = context.self_attrs.ser_bound.clone();
+    let (generics, fields) = encode_type(
+        context,
+        input.generics.clone(),
+        input.data,
+        &input.ident,
+        tagged.is_some(),
+    );
+    let generics = resolve_bounds(&bound, input.generics, generics);
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
-    let s = quote! {
-        #[automatically_derived]
-        impl#impl_generics ::binary::BinSerialize for #ident#ty_generics #where_clause {
-            fn encode_to(&self, buf: &mut dyn ::binary::BinWrite, attrs: ::binary::attr::Attrs) -> ::binary::Result<()> {
-                #fields
-                Ok(())
+    let imp = if let Some((tag_ty, tag_of)) = tagged {
+        quote! {
+            impl#impl_generics _binary::BinSerializeTagged for #ident#ty_generics #where_clause {
+                type Tag = #tag_ty;
+                #[allow(unused_variables)]
+                fn tag(&self) -> Self::Tag {
+                    #tag_of
+                }
+                fn encode_untagged(&self, buf: &mut dyn _binary::BinWrite, attrs: _binary::attr::Attrs, tag: &Self::Tag) -> _binary::Result<()> {
+                    #fields
+                    Ok(())
+                }
+            }
+            impl#impl_generics _binary::BinSerialize for #ident#ty_generics #where_clause {
+                fn encode_to(&self, buf: &mut dyn _binary::BinWrite, attrs: _binary::attr::Attrs) -> _binary::Result<()> {
+                    let tag = _binary::BinSerializeTagged::tag(self);
+                    _binary::BinSerialize::encode_to(&tag, buf, #tag_attrs)?;
+                    _binary::BinSerializeTagged::encode_untagged(self, buf, attrs, &tag)
+                }
+            }
+        }
+    } else if imports.is_empty() {
+        quote! {
+            impl#impl_generics _binary::BinSerialize for #ident#ty_generics #where_clause {
+                fn encode_to(&self, buf: &mut dyn _binary::BinWrite, attrs: _binary::attr::Attrs) -> _binary::Result<()> {
+                    #fields
+                    Ok(())
+                }
+            }
+        }
+    } else {
+        let (names, tys) = split_imports(&imports);
+        quote! {
+            impl#impl_generics _binary::BinSerializeArgs for #ident#ty_generics #where_clause {
+                type Args = (#(#tys,)*);
+                fn encode_with(&self, buf: &mut dyn _binary::BinWrite, attrs: _binary::attr::Attrs, args: Self::Args) -> _binary::Result<()> {
+                    #[allow(unused_variables)]
+                    let (#(#names,)*) = args;
+                    #fields
+                    Ok(())
+                }
             }
         }
-        #attr_errors
     };
+    let s = wrap_impl(&krate, imp, attr_errors);
     #[cfg(feature = "debug_prints")]
     println!("=====\n{}\n=====", s);
     s.into()
@@ -61,25 +162,222 @@ pub fn derive_bindeserialize(input: TokenStream) -> TokenStream {
     println!("derive(BinDeserialize) for {}", input.ident);
     let (context, attr_errors) = Context::from_input(&input);
     let ident = &input.ident;
-    let (generics, fields) = decode_type(context, input.generics, input.data, &input.ident);
+    let imports = context.self_attrs.imports.clone();
+    let krate = context.self_attrs.krate.clone();
+    let tag_attrs = helpers::build_tag_attrs(context.self_attrs.tag_le);
+    let borrowed = context.borrowed;
+    // Types borrowing from their input are only BinDeserializeBorrowed, reading their own tag
+    let tag_ty = if borrowed {
+        None
+    } else {
+        enum_tag_type(&context, &input.data, ident)
+    };
+    let bound = context.self_attrs.de_bound.clone();
+    let (generics, fields) = decode_type(
+        context,
+        input.generics.clone(),
+        input.data,
+        &input.ident,
+        tag_ty.is_some(),
+    );
+    let generics = resolve_bounds(&bound, input.generics, generics);
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
-    let s = quote! {
-        #[automatically_derived]
-        impl#impl_generics ::binary::BinDeserialize for #ident#ty_generics #where_clause {
-            fn decode_from(buf: &mut dyn ::binary::BinRead, attrs: ::binary::attr::Attrs) -> ::binary::Result<Self> {
-                Ok({
-                    #fields
-                })
+    let imp = if borrowed {
+        let (generics, errors) = borrowed_generics(&generics, &imports);
+        let (impl_generics, _, where_clause) = generics.split_for_impl();
+        quote! {
+            #errors
+            impl#impl_generics _binary::BinDeserializeBorrowed<'de> for #ident#ty_generics #where_clause {
+                fn decode_borrowed(buf: &mut dyn _binary::BorrowRead<'de>, attrs: _binary::attr::Attrs) -> _binary::Result<Self> {
+                    Ok({
+                        #fields
+                    })
+                }
+            }
+        }
+    } else if let Some(tag_ty) = tag_ty {
+        quote! {
+            impl#impl_generics _binary::BinDeserializeTagged for #ident#ty_generics #where_clause {
+                type Tag = #tag_ty;
+                fn decode_untagged(buf: &mut dyn _binary::BinRead, attrs: _binary::attr::Attrs, tag: Self::Tag) -> _binary::Result<Self> {
+                    Ok({
+                        #fields
+                    })
+                }
+            }
+            impl#impl_generics _binary::BinDeserialize for #ident#ty_generics #where_clause {
+                fn decode_from(buf: &mut dyn _binary::BinRead, attrs: _binary::attr::Attrs) -> _binary::Result<Self> {
+                    let tag = <#tag_ty as _binary::BinDeserialize>::decode_from(buf, #tag_attrs)?;
+                    <Self as _binary::BinDeserializeTagged>::decode_untagged(buf, attrs, tag)
+                }
+            }
+        }
+    } else if imports.is_empty() {
+        quote! {
+            impl#impl_generics _binary::BinDeserialize for #ident#ty_generics #where_clause {
+                fn decode_from(buf: &mut dyn _binary::BinRead, attrs: _binary::attr::Attrs) -> _binary::Result<Self> {
+                    Ok({
+                        #fields
+                    })
+                }
+            }
+        }
+    } else {
+        let (names, tys) = split_imports(&imports);
+        quote! {
+            impl#impl_generics _binary::BinDeserializeArgs for #ident#ty_generics #where_clause {
+                type Args = (#(#tys,)*);
+                fn decode_with(buf: &mut dyn _binary::BinRead, attrs: _binary::attr::Attrs, args: Self::Args) -> _binary::Result<Self> {
+                    #[allow(unused_variables)]
+                    let (#(#names,)*) = args;
+                    Ok({
+                        #fields
+                    })
+                }
             }
         }
-        #attr_errors
     };
+    let s = wrap_impl(&krate, imp, attr_errors);
     #[cfg(feature = "debug_prints")]
     println!("=====\n{}\n=====", s);
     s.into()
 }
 
+// Generates FooView<'a> and FooViewMut<'a>, which read and write the fields of a fixed-layout
+// struct Foo directly in a byte buffer
+#[proc_macro_derive(BinView, attributes(binary))]
+pub fn derive_binview(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    view::derive(input).into()
+}
+
+// Implements BinSize, giving bounds on the size a type encodes to, and checks #[binary(size = N)]
+#[proc_macro_derive(BinSize, attributes(binary))]
+pub fn derive_binsize(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    size::derive(input).into()
+}
+
+// The tag type of an enum that can be (de)serialized with its tag given separately, through
+// Bin(De)SerializeTagged. Enums with imports cannot, nor can ones whose tags are in error.
+fn enum_tag_type(context: &Context, data: &Data, ident: &Ident) -> Option<Type> {
+    match data {
+        Data::Enum(e) if context.self_attrs.imports.is_empty() => {
+            resolve_variant_tags(context, &e.variants, ident)
+                .0
+                .map(|tags| tags.ty)
+        }
+        _ => None,
+    }
+}
+
+// The body of BinSerializeTagged::tag: the first tag of the variant, or its #[binary(tag)] field
+fn build_enum_tag_of(context: &Context, data: &Data, ident: &Ident) -> TokenStream2 {
+    let e = match data {
+        Data::Enum(e) => e,
+        _ => return quote! {},
+    };
+    let tags = match resolve_variant_tags(context, &e.variants, ident).0 {
+        Some(tags) => tags,
+        None => return quote! {},
+    };
+    let arms = e.variants.iter().enumerate().map(|(vi, v)| {
+        let (context, _) = context.recurse_into(Level::Variant, v.ident.to_string(), &v.attrs);
+        let name = &v.ident;
+        let fields = pattern_fields(&v.fields);
+        let tag = if let Some((i, f)) = find_tag_field(&context, &v.fields) {
+            let value = encode_field_access(&context, f, i);
+            quote! { ::std::clone::Clone::clone(&#value) }
+        } else {
+            // A missing tag is reported by the encode body
+            tags.build_first(vi)
+                .unwrap_or_else(|| quote! { unreachable!() })
+        };
+        quote! { #ident::#name#fields => #tag, }
+    });
+    let consts = tags.build_consts();
+    quote! {
+        #consts
+        match self {
+            #(#arms)*
+        }
+    }
+}
+
+// Places the impls in an anonymous const, where generated code reaches the runtime crate as
+// `_binary`: the `binary` crate, or the path given by #[binary(crate = "...")]
+fn wrap_impl(krate: &Option<Path>, imp: TokenStream2, attr_errors: TokenStream2) -> TokenStream2 {
+    let alias = match krate {
+        Some(path) => quote! { use #path as _binary; },
+        None => quote! {
+            #[allow(unused_extern_crates)]
+            extern crate binary as _binary;
+        },
+    };
+    quote! {
+        const _: () = {
+            #alias
+            #[automatically_derived]
+            #imp
+        };
+        #attr_errors
+    }
+}
+
+// The generics of a BinDeserializeBorrowed impl: those of the type, with the lifetime 'de of the
+// input outliving each of its own. A lifetime the type names 'de is taken to be the input's.
+fn borrowed_generics(generics: &Generics, imports: &[(Ident, Type)]) -> (Generics, TokenStream2) {
+    let errors = imports.first().map(|(name, _)| {
+        let span = name.span();
+        quote_spanned! {span=>
+            compile_error!("types with lifetime parameters are decoded by borrowing from their input, and cannot have #[binary(import = ...)]");
+        }
+    });
+    let mut generics = generics.clone();
+    let lifetimes: Vec<_> = generics
+        .lifetimes()
+        .map(|l| l.lifetime.clone())
+        .filter(|l| l.ident != "de")
+        .collect();
+    if lifetimes.len() == generics.lifetimes().count() {
+        generics.params.insert(0, parse_quote! { 'de });
+    }
+    let where_clause = generics.make_where_clause();
+    for l in lifetimes {
+        where_clause.predicates.push(parse_quote! { 'de: #l });
+    }
+    (generics, quote! { #errors })
+}
+
+fn split_imports(imports: &[(Ident, Type)]) -> (Vec<&Ident>, Vec<&Type>) {
+    imports.iter().map(|(name, ty)| (name, ty)).unzip()
+}
+
+// The generics of the impl: those inferred from the fields, unless the type's bound = "..."
+// replaces them
+fn resolve_bounds(
+    bound: &Option<Vec<WherePredicate>>,
+    declared: Generics,
+    inferred: Generics,
+) -> Generics {
+    match bound {
+        Some(bound) => {
+            let mut generics = declared;
+            extend_bounds(&mut generics, bound);
+            generics
+        }
+        None => inferred,
+    }
+}
+
+fn extend_bounds(generics: &mut Generics, bound: &[WherePredicate]) {
+    generics
+        .make_where_clause()
+        .predicates
+        .extend(bound.iter().cloned());
+}
+
 fn make_generic_bound(ty: Type, bound: Path) -> WherePredicate {
     syn::WherePredicate::Type(syn::PredicateType {
         lifetimes: None,
@@ -96,371 +394,1577 @@ fn make_generic_bound(ty: Type, bound: Path) -> WherePredicate {
     })
 }
 
+// Builds the body of the encode function. With `untagged`, an enum's body is that of
+// BinSerializeTagged::encode_untagged, which checks the variant against `tag` in place of
+// writing it.
 fn encode_type(
     parent_context: Context,
     mut generics: Generics,
     data: Data,
     ident: &Ident,
+    untagged: bool,
 ) -> (Generics, TokenStream2) {
     match data {
         Data::Struct(s) => {
-            let (generics, fields) = encode_fields(&parent_context, generics, s.fields);
+            let validate = build_encode_validate(&parent_context, quote! { self });
+            let (generics, fields) = encode_fields(&parent_context, generics, s.fields, None, None);
+            let fields = build_nest_encode(&parent_context.self_attrs, quote! { #(#fields)* });
             (
                 generics,
                 quote! {
-                    #(#fields)*
+                    #validate
+                    #fields
                 },
             )
         }
         Data::Enum(e) => {
             let mut variants: Vec<TokenStream2> = vec![];
 
-            if parent_context.self_attrs.tag_ty.is_none() {
-                let span = ident.span();
-                return (
-                    generics,
-                    quote_spanned! {span=>
-                        compile_error!("no tag type defined; enums deriving BinSerialize or BinDeserialize must have repr(uN) or repr(iN)");
-                    },
-                );
-            }
-
-            let tag_ty = parent_context.self_attrs.tag_ty.clone().unwrap();
-            let mut tag = 0u64;
+            let (tags, tag_errors) = resolve_variant_tags(&parent_context, &e.variants, ident);
+            let tags = match tags {
+                Some(t) => t,
+                None => return (generics, tag_errors),
+            };
+            variants.push(tag_errors);
             let mut default_defined = false;
 
-            for v in e.variants {
-                let (context, attr_errors) = parent_context.recurse_into(Level::Variant, &v.attrs);
+            for (vi, v) in e.variants.into_iter().enumerate() {
+                let (context, attr_errors) =
+                    parent_context.recurse_into(Level::Variant, v.ident.to_string(), &v.attrs);
                 let name = &v.ident;
+                let attrs = helpers::build_tag_attrs(parent_context.self_attrs.tag_le);
+                let span = v.span();
+                let tag_field = find_tag_field(&context, &v.fields);
+                let tag_index = tag_field.map(|(i, _)| i);
+                let default_errors = if context.self_attrs.tag_default {
+                    let errors = build_default_errors(&v, tag_index, default_defined);
+                    default_defined = true;
+                    Some(errors)
+                } else {
+                    None
+                };
 
-                if context.self_attrs.tag_default {
-                    let errors = if default_defined {
-                        let span = name.span();
-                        Some(quote_spanned! {span=>
-                            compile_error!("multiple variants with a #[binary(default)] attribute found");
-                        })
-                    } else {
-                        None
+                // Checks that the tag `value` selects this variant. The default variant's tag
+                // must not be one of the others', or it would decode as that variant.
+                let variant_name = &context.name;
+                let check = |value: TokenStream2| {
+                    let value = tags.build_scrutinee(value);
+                    let mismatch = quote! {
+                        return Err(_binary::BinError::TagMismatch(#variant_name.to_string()))
                     };
-                    default_defined = true;
-                    let tag_ty = &tag_ty.0;
-                    let attrs = helpers::build_tag_attrs(parent_context.self_attrs.tag_le);
-                    variants.push(quote! {
-                        #ident::#name(tag, vec) => {
-                            #attr_errors
-                            #errors
-                            <#tag_ty as ::binary::BinSerialize>::encode_to(tag, buf, #attrs)?;
-                            <::std::vec::Vec<u8> as ::binary::BinSerialize>::encode_to(vec, buf, #attrs)?;
+                    if default_errors.is_none() {
+                        let pattern = tags.build_pattern(vi);
+                        quote! {
+                            match #value {
+                                #pattern => {}
+                                _ => #mismatch,
+                            }
                         }
-                    });
-                } else {
-                    tag = match helpers::find_discriminant(&v) {
-                        Ok(Some(v)) => v,
-                        Ok(None) => tag,
-                        Err(e) => {
-                            variants.push(e);
-                            tag
+                    } else if let Some(others) = tags.build_claimed_pattern() {
+                        quote! {
+                            match #value {
+                                #others => #mismatch,
+                                _ => {}
+                            }
                         }
-                    };
-                    let header = {
-                        let tag_lit = LitInt::new(tag, tag_ty.1.clone(), v.span());
-                        let attrs = helpers::build_tag_attrs(parent_context.self_attrs.tag_le);
+                    } else {
+                        quote! {}
+                    }
+                };
+                let range_error = match (tag_field, tags.variants[vi].first()) {
+                    (None, Some(helpers::VariantTag::Range(..))) => quote_spanned! {span=>
+                        compile_error!("a variant with a range of tags needs a #[binary(tag)] field to hold its tag");
+                    },
+                    _ => quote! {},
+                };
+                let header = if untagged {
+                    let check = check(quote! { *tag });
+                    let field_check = tag_field.map(|(i, f)| {
+                        let value = encode_field_access(&context, f, i);
                         quote! {
-                            ::binary::BinSerialize::encode_to(&#tag_lit, buf, #attrs)?;
+                            if #value != *tag {
+                                return Err(_binary::BinError::TagMismatch(#variant_name.to_string()));
+                            }
                         }
-                    };
-                    tag += 1;
-
-                    let fields = pattern_fields(&v.fields);
-
-                    let (newgen, encodes) = encode_fields(&context, generics, v.fields);
-                    let mut encodes = quote! { #(#encodes)* };
-
-                    if context.self_attrs.nest {
-                        let attrs = helpers::build_nest_attrs(
-                            context.self_attrs.nest_le,
-                            context.self_attrs.nest_ty.unwrap(),
-                        );
-                        encodes = quote! {
-                            let nested = {
-                                let mut v = vec![];
-                                let buf: &mut dyn ::binary::BinWrite = &mut v;
-                                #encodes
-                                v
-                            };
-                            ::binary::BinSerialize::encode_to(&nested, buf, #attrs)?;
+                    });
+                    quote! {
+                        #range_error
+                        #check
+                        #field_check
+                    }
+                } else {
+                    match tag_field {
+                        Some((i, f)) => {
+                            let value = encode_field_access(&context, f, i);
+                            let check = check(value.clone());
+                            quote! {
+                                #check
+                                _binary::BinSerialize::encode_to(&#value, buf, #attrs)?;
+                            }
                         }
+                        None => match tags.build_first(vi) {
+                            Some(tag) => {
+                                let ty = &tags.ty;
+                                quote! {
+                                    <#ty as _binary::BinSerialize>::encode_to(&#tag, buf, #attrs)?;
+                                }
+                            }
+                            None => range_error,
+                        },
                     }
+                };
 
-                    generics = newgen;
+                let fields = pattern_fields(&v.fields);
 
-                    variants.push(quote! {
-                        #ident::#name#fields => {
-                            #header
-                            #(#encodes)*
-                            #attr_errors
-                        }
-                    });
-                }
+                // A nested variant's fields are counted from the start of their own buffer
+                let tag_ty = if context.self_attrs.nest {
+                    None
+                } else {
+                    Some(&tags.ty)
+                };
+                let (newgen, encodes) =
+                    encode_fields(&context, generics, v.fields, tag_index, tag_ty);
+                let encodes = build_nest_encode(&context.self_attrs, quote! { #(#encodes)* });
+                generics = newgen;
+
+                variants.push(quote! {
+                    #ident::#name#fields => {
+                        #default_errors
+                        #header
+                        #(#encodes)*
+                        #attr_errors
+                    }
+                });
             }
+            let validate = build_encode_validate(&parent_context, quote! { self });
+            let consts = tags.build_consts();
             let encode = quote! {
+                #validate
+                #consts
                 match self {
                     #(#variants)*
                 }
             };
             (generics, encode)
         }
-        _ => unimplemented!(),
+        Data::Union(u) => encode_union(&parent_context, generics, u, ident),
     }
 }
 
-fn pattern_fields(f: &Fields) -> TokenStream2 {
-    match f {
-        Fields::Named(f) => {
-            let mut v = vec![];
-            for f in &f.named {
-                let base_ident = f.ident.clone().unwrap();
-                let ident = Ident::new(
-                    &format!("self_{}", f.ident.clone().unwrap()),
-                    f.ident.span(),
-                );
-                v.push(quote! { #base_ident: #ident });
+// The tags of an enum's variants, and the type they are (de)serialized as: [u8; N] for byte
+// string tags, or else the enum's tag type. The #[binary(default)] variant has no tags.
+struct EnumTags {
+    variants: Vec<Vec<helpers::VariantTag>>,
+    ty: Type,
+    bytes: bool,
+}
+
+impl EnumTags {
+    // The local const holding an integer tag; tags are evaluated in the generated code, so that
+    // they may be constant expressions
+    fn const_ident(vi: usize, j: usize, end: bool) -> Ident {
+        let name = if end {
+            format!("BINARY_TAG_{}_{}_END", vi, j)
+        } else {
+            format!("BINARY_TAG_{}_{}", vi, j)
+        };
+        Ident::new(&name, proc_macro2::Span::call_site())
+    }
+
+    // Declares the consts for every integer tag
+    fn build_consts(&self) -> TokenStream2 {
+        let ty = &self.ty;
+        let mut consts = vec![];
+        for (vi, tags) in self.variants.iter().enumerate() {
+            for (j, tag) in tags.iter().enumerate() {
+                let mut declare = |end, expr: &TokenStream2| {
+                    let name = Self::const_ident(vi, j, end);
+                    consts.push(quote! {
+                        #[allow(dead_code)]
+                        const #name: #ty = #expr;
+                    });
+                };
+                match tag {
+                    helpers::VariantTag::Int(expr) => declare(false, expr),
+                    helpers::VariantTag::Range(lo, hi) => {
+                        declare(false, lo);
+                        declare(true, hi);
+                    }
+                    helpers::VariantTag::Bytes(_) => {}
+                }
             }
-            quote! { { #(#v),* } }
         }
-        Fields::Unnamed(f) => {
-            let mut v = vec![];
-            for i in 0..f.unnamed.len() {
-                let ident = Ident::new(&format!("self_{}", i), f.unnamed[i].span());
-                v.push(quote! { #ident });
+        quote! { #(#consts)* }
+    }
+
+    // The pattern matching the tags of variant vi, or None if it has none
+    fn build_pattern(&self, vi: usize) -> Option<TokenStream2> {
+        let tags = &self.variants[vi];
+        if tags.is_empty() {
+            return None;
+        }
+        let patterns = tags.iter().enumerate().map(|(j, t)| match t {
+            helpers::VariantTag::Int(_) => {
+                let name = Self::const_ident(vi, j, false);
+                quote! { #name }
             }
-            quote! { ( #(#v),* ) }
+            helpers::VariantTag::Range(..) => {
+                let lo = Self::const_ident(vi, j, false);
+                let hi = Self::const_ident(vi, j, true);
+                quote! { #lo..=#hi }
+            }
+            helpers::VariantTag::Bytes(b) => {
+                let b = LitByteStr::new(b, proc_macro2::Span::call_site());
+                quote! { #b }
+            }
+        });
+        Some(quote! { #(#patterns)|* })
+    }
+
+    // The pattern matching the tags of every variant, or None if there are none
+    fn build_claimed_pattern(&self) -> Option<TokenStream2> {
+        let patterns: Vec<_> = (0..self.variants.len())
+            .filter_map(|vi| self.build_pattern(vi))
+            .collect();
+        if patterns.is_empty() {
+            None
+        } else {
+            Some(quote! { #(#patterns)|* })
+        }
+    }
+
+    // The value of the first tag of variant vi, unless it has none or it is a range
+    fn build_first(&self, vi: usize) -> Option<TokenStream2> {
+        match self.variants[vi].first()? {
+            helpers::VariantTag::Int(_) => {
+                let name = Self::const_ident(vi, 0, false);
+                Some(quote! { #name })
+            }
+            helpers::VariantTag::Bytes(b) => {
+                let b = LitByteStr::new(b, proc_macro2::Span::call_site());
+                Some(quote! { *#b })
+            }
+            helpers::VariantTag::Range(..) => None,
+        }
+    }
+
+    // The scrutinee matching the tag `value` against patterns. Integer tags are matched by
+    // value, as consts cannot match through references; byte strings by reference.
+    fn build_scrutinee(&self, value: TokenStream2) -> TokenStream2 {
+        if self.bytes {
+            quote! { &#value }
+        } else {
+            quote! { #value }
         }
-        Fields::Unit => quote! {},
     }
 }
 
-fn decode_type(
-    context: Context,
-    mut generics: Generics,
-    data: Data,
+// Resolves the tags of each variant of an enum, from tag = ..., its discriminant, or else
+// following on from the previous variant's. If the enum has no tag type, the errors say why.
+fn resolve_variant_tags(
+    context: &Context,
+    variants: &Punctuated<Variant, Token![,]>,
     ident: &Ident,
-) -> (Generics, TokenStream2) {
-    match data {
-        Data::Struct(s) => {
-            let (generics, decodes, transfers, errors) =
-                decode_fields(&context, generics, s.fields);
-            (
-                generics,
-                quote! {
-                    #errors
-                    #decodes
-                    Self #transfers
-                },
-            )
+) -> (Option<EnumTags>, TokenStream2) {
+    let mut all = vec![];
+    let mut errors = vec![];
+    let mut next = Some(quote! { 0 });
+    for (vi, v) in variants.iter().enumerate() {
+        let (context, _) = context.recurse_into(Level::Variant, v.ident.to_string(), &v.attrs);
+        if context.self_attrs.tag_default {
+            all.push(vec![]);
+            continue;
         }
-        Data::Enum(e) => {
-            let mut variants: Vec<TokenStream2> = vec![];
-
-            let header = if context.self_attrs.tag_ty.is_none() {
-                let span = ident.span();
-                quote_spanned! {span=>
-                    compile_error!("no tag type defined; enums deriving BinSerialize or BinDeserialize must have repr(uN) or repr(iN)");
-                    let variant = 0;
-                }
-            } else {
-                let tag_ty = context.self_attrs.tag_ty.clone().unwrap().0;
-                let attrs = helpers::build_tag_attrs(context.self_attrs.tag_le);
-                quote! {
-                    let variant = <#tag_ty as ::binary::BinDeserialize>::decode_from(buf, #attrs)?;
+        let tags = if !context.self_attrs.tags.is_empty() {
+            context.self_attrs.tags
+        } else {
+            match helpers::find_discriminant(v).or_else(|| next.clone()) {
+                Some(d) => vec![helpers::VariantTag::Int(d)],
+                None => {
+                    let span = v.span();
+                    errors.push(quote_spanned! {span=>
+                        compile_error!("a variant following one with a byte string tag must have #[binary(tag = ...)]");
+                    });
+                    vec![]
                 }
-            };
+            }
+        };
+        next = match tags.last() {
+            Some(helpers::VariantTag::Int(_)) => {
+                let prev = EnumTags::const_ident(vi, tags.len() - 1, false);
+                Some(quote! { #prev + 1 })
+            }
+            Some(helpers::VariantTag::Range(..)) => {
+                let prev = EnumTags::const_ident(vi, tags.len() - 1, true);
+                Some(quote! { #prev + 1 })
+            }
+            _ => None,
+        };
+        all.push(tags);
+    }
 
-            let tag_ty = context.self_attrs.tag_ty.clone().unwrap();
-            let mut tag = 0u64;
-            let mut default_variant = None;
+    let span = ident.span();
+    let byte_lengths: Vec<_> = all
+        .iter()
+        .flatten()
+        .map(|t| match t {
+            helpers::VariantTag::Bytes(b) => Some(b.len()),
+            _ => None,
+        })
+        .collect();
+    let ty = match byte_lengths.first() {
+        Some(Some(n)) => {
+            if byte_lengths.iter().any(|l| *l != Some(*n)) {
+                errors.push(quote_spanned! {span=>
+                    compile_error!("an enum's tags must be all integers, or all byte strings of the same length");
+                });
+            }
+            Some(parse_quote! { [u8; #n] })
+        }
+        _ => {
+            if byte_lengths.iter().any(Option::is_some) {
+                errors.push(quote_spanned! {span=>
+                    compile_error!("an enum's tags must be all integers, or all byte strings of the same length");
+                });
+            }
+            if context.self_attrs.tag_ty.is_none() {
+                errors.push(quote_spanned! {span=>
+                    compile_error!("no tag type defined; enums deriving BinSerialize or BinDeserialize must have repr(uN) or repr(iN)");
+                });
+            }
+            context.self_attrs.tag_ty.clone().map(|(ty, _)| ty)
+        }
+    };
+    errors.extend(check_variant_tags(context, variants, &all));
+    // Any error would be followed by confusing ones from the generated code
+    let tags = ty.filter(|_| errors.is_empty()).map(|ty| EnumTags {
+        variants: all,
+        bytes: matches!(ty, Type::Array(_)),
+        ty,
+    });
+    (tags, quote! { #(#errors)* })
+}
 
-            for v in e.variants {
-                let (context, attr_errors) = context.recurse_into(Level::Variant, &v.attrs);
-                let name = &v.ident;
-                if context.self_attrs.tag_default {
-                    let errors = default_variant.as_ref().map(|_| {
-                        let span = name.span();
-                        quote_spanned!{span=>
-                            compile_error!("multiple variants with a #[binary(default)] attribute found");
-                        }
-                    });
-                    default_variant = Some(quote! {
-                        _ => {
-                            #errors
-                            let vec = <::std::vec::Vec<u8> as ::binary::BinDeserialize>::decode_from(buf, ::binary::attr::Attrs::zero())?;
-                            #ident::#name(variant, vec)
-                        }
-                    });
-                } else {
-                    tag = match helpers::find_discriminant(&v) {
-                        Ok(Some(v)) => v,
-                        Ok(None) => tag,
-                        Err(e) => {
-                            variants.push(e);
-                            tag
-                        }
-                    };
-                    let tag_lit = LitInt::new(tag, tag_ty.1.clone(), v.span());
-                    tag += 1;
-                    let (newgen, decodes, transfers, errors) =
-                        decode_fields(&context, generics, v.fields);
-                    generics = newgen;
+// The value of a tag, where the derive can evaluate it
+enum TagValue {
+    Ints(i128, i128), // inclusive
+    Bytes(Vec<u8>),
+}
 
-                    let pre = if context.self_attrs.nest {
-                        let attrs = helpers::build_nest_attrs(
-                            context.self_attrs.nest_le,
-                            context.self_attrs.nest_ty.unwrap(),
-                        );
-                        Some(quote! {
-                            let v = <Vec<u8> as ::binary::BinDeserialize>::decode_from(buf, #attrs)?;
-                            let buf: &mut dyn ::binary::BinRead = &mut v.as_slice();
-                        })
-                    } else {
-                        None
-                    };
+impl TagValue {
+    // A tag both values have in common, if they overlap
+    fn overlap(&self, other: &TagValue) -> Option<String> {
+        match (self, other) {
+            (TagValue::Ints(lo, hi), TagValue::Ints(other_lo, other_hi))
+                if lo <= other_hi && other_lo <= hi =>
+            {
+                Some(lo.max(other_lo).to_string())
+            }
+            (TagValue::Bytes(b), TagValue::Bytes(other_b)) if b == other_b => {
+                let escaped: String = b
+                    .iter()
+                    .flat_map(|c| std::ascii::escape_default(*c))
+                    .map(char::from)
+                    .collect();
+                Some(format!("b\"{}\"", escaped))
+            }
+            _ => None,
+        }
+    }
+}
 
-                    variants.push(quote! {
-                        #tag_lit => {
-                            #attr_errors
-                            #errors
-                            #pre
-                            #decodes
-                            #ident::#name#transfers
-                        }
+// Reports tags that do not fit in the enum's tag type, and tags shared by two variants, as far
+// as they can be evaluated here. Tags naming consts are only checked by the compiler.
+fn check_variant_tags(
+    context: &Context,
+    variants: &Punctuated<Variant, Token![,]>,
+    all: &[Vec<helpers::VariantTag>],
+) -> Vec<TokenStream2> {
+    let tag_ty = context.self_attrs.tag_ty.as_ref();
+    let range = tag_ty.and_then(|(_, suffix)| helpers::int_range(suffix));
+    let mut errors = vec![];
+    let mut values: Vec<(usize, TagValue)> = vec![];
+    let mut next = Some(0);
+    for (vi, (v, tags)) in variants.iter().zip(all).enumerate() {
+        let (context, _) = context.recurse_into(Level::Variant, v.ident.to_string(), &v.attrs);
+        let implicit = context.self_attrs.tags.is_empty() && v.discriminant.is_none();
+        let span = v.ident.span();
+        for tag in tags {
+            let value = match tag {
+                helpers::VariantTag::Int(_) if implicit => next.map(|n| TagValue::Ints(n, n)),
+                helpers::VariantTag::Int(expr) => {
+                    helpers::eval_tag(expr).map(|n| TagValue::Ints(n, n))
+                }
+                helpers::VariantTag::Range(lo, hi) => {
+                    match (helpers::eval_tag(lo), helpers::eval_tag(hi)) {
+                        (Some(lo), Some(hi)) => Some(TagValue::Ints(lo, hi)),
+                        _ => None,
+                    }
+                }
+                helpers::VariantTag::Bytes(b) => Some(TagValue::Bytes(b.clone())),
+            };
+            next = match &value {
+                Some(TagValue::Ints(_, hi)) => Some(hi + 1),
+                _ => None,
+            };
+            let value = match value {
+                Some(value) => value,
+                None => continue,
+            };
+            if let (TagValue::Ints(lo, hi), Some((min, max))) = (&value, range) {
+                let out = [*lo, *hi].iter().copied().find(|n| *n < min || *n > max);
+                if let Some(n) = out {
+                    let ty = &tag_ty.unwrap().0;
+                    let message =
+                        format!("tag {} does not fit in the tag type {}", n, quote! { #ty });
+                    errors.push(quote_spanned! {span=>
+                        compile_error!(#message);
                     });
                 }
             }
-            if default_variant.is_none() {
-                default_variant = Some(quote! {
-                    _ => return Err(::binary::BinError::VariantNotMatched(variant as u64))
-                })
+            let duplicate = values
+                .iter()
+                .find_map(|(other, o)| o.overlap(&value).map(|tag| (*other, tag)));
+            if let Some((other, tag)) = duplicate {
+                let other_variant = &variants[other];
+                let message = format!(
+                    "tag {} is already used by variant `{}`",
+                    tag, other_variant.ident
+                );
+                errors.push(quote_spanned! {span=>
+                    compile_error!(#message);
+                });
+                let other_span = other_variant.ident.span();
+                let message = format!("tag {} is used again by variant `{}`", tag, v.ident);
+                errors.push(quote_spanned! {other_span=>
+                    compile_error!(#message);
+                });
             }
-            let decode = quote! {
-                #header
-                match variant {
-                    #(#variants)*
-                    #default_variant
-                }
-            };
-            (generics, decode)
+            values.push((vi, value));
         }
-        _ => unimplemented!(),
     }
+    errors
 }
 
-fn encode_fields(
+// The variant field holding its tag, if any, and its index: the one with #[binary(tag)], or
+// else the first field of the #[binary(default)] variant
+fn find_tag_field<'a>(context: &Context, fields: &'a Fields) -> Option<(usize, &'a Field)> {
+    let marked = fields.iter().enumerate().find(|(i, f)| {
+        let (context, _) = context.recurse_into(Level::Field, field_name(&f.ident, *i), &f.attrs);
+        context.self_attrs.tag_field
+    });
+    if marked.is_none() && context.self_attrs.tag_default {
+        return fields.iter().enumerate().next();
+    }
+    marked
+}
+
+// Errors for a #[binary(default)] variant that is not the first, or has no field for its tag
+fn build_default_errors(
+    v: &Variant,
+    tag_field: Option<usize>,
+    default_defined: bool,
+) -> TokenStream2 {
+    let span = v.ident.span();
+    let multiple = if default_defined {
+        Some(quote_spanned! {span=>
+            compile_error!("multiple variants with a #[binary(default)] attribute found");
+        })
+    } else {
+        None
+    };
+    let no_tag = if tag_field.is_none() {
+        Some(quote_spanned! {span=>
+            compile_error!("a #[binary(default)] variant needs a field to hold its tag");
+        })
+    } else {
+        None
+    };
+    quote! {
+        #multiple
+        #no_tag
+    }
+}
+
+fn encode_union(
     context: &Context,
     mut generics: Generics,
-    fields: Fields,
-) -> (Generics, Vec<TokenStream2>) {
-    let mut encodes = vec![];
-    let mut tail = None;
-    let fields = match fields {
-        Fields::Named(n) => n.named,
-        Fields::Unnamed(u) => u.unnamed,
-        Fields::Unit => return (generics, vec![]),
+    data: DataUnion,
+    ident: &Ident,
+) -> (Generics, TokenStream2) {
+    if context.self_attrs.raw {
+        let checks = raw_union_checks(context, &mut generics, &data, ident);
+        return (
+            generics,
+            quote! {
+                #checks
+                // Safety: raw_union_checks ensures every member is RawBytes and fills the union, so
+                // all of its bytes are initialized whichever member was written
+                let bytes = unsafe {
+                    ::std::slice::from_raw_parts(
+                        self as *const Self as *const u8,
+                        ::std::mem::size_of::<Self>(),
+                    )
+                };
+                ::std::io::Write::write_all(buf, bytes)?;
+            },
+        );
+    }
+    let select = match &context.self_attrs.select {
+        Some(v) => v,
+        None => return (generics, union_selector_error(ident)),
     };
-    let mut flags_ty = None;
-    let mut warned_for_no_flags = false;
-    for (i, f) in fields.into_iter().enumerate() {
-        let span = f.span();
-        let (context, attr_errors) = context.recurse_into(Level::Field, &f.attrs);
 
+    let mut members = vec![];
+    for (i, f) in data.fields.named.into_iter().enumerate() {
+        let (context, attr_errors) =
+            context.recurse_into(Level::Field, field_name(&f.ident, i), &f.attrs);
+        let name = &f.ident;
+        let tag = match &context.self_attrs.tag_value {
+            Some(v) => v,
+            None => {
+                members.push(union_tag_error(&f));
+                continue;
+            }
+        };
+
+        if let Some(bound) = &context.self_attrs.ser_bound {
+            extend_bounds(&mut generics, bound);
+        } else if context.self_attrs.serialize_with.is_none() {
+            generics
+                .make_where_clause()
+                .predicates
+                .push(make_generic_bound(
+                    match &context.self_attrs.into {
+                        Some((raw, _)) => raw.clone(),
+                        None => f.ty.clone(),
+                    },
+                    parse_quote! {_binary::BinSerialize},
+                ));
+        }
+
+        let attrs = context.build_attrs();
+        let encode = encode_value(&context, quote! { value }, &attrs);
+        members.push(quote! {
+            #tag => {
+                #attr_errors
+                // Safety: by opting into unsafe_select, the union's author vouches that the
+                // selector names the member last written
+                let value = unsafe { &self.#name };
+                #encode
+            }
+        });
+    }
+
+    (
+        generics,
+        quote! {
+            match #select {
+                #(#members)*
+                other => return Err(_binary::BinError::VariantNotMatched(other as u64)),
+            }
+        },
+    )
+}
+
+fn union_selector_error(ident: &Ident) -> TokenStream2 {
+    let span = ident.span();
+    quote_spanned! {span=>
+        compile_error!("unions deriving BinSerialize or BinDeserialize must have #[binary(raw)] or #[binary(unsafe_select = \"...\")]");
+    }
+}
+
+// Checks that a raw union can be (de)serialized as its bytes: it must be repr(C) or
+// repr(transparent), so every member starts at its first byte, and every member must be RawBytes
+// and as large as the union, so whichever was written leaves no byte uninitialized
+fn raw_union_checks(
+    context: &Context,
+    generics: &mut Generics,
+    data: &DataUnion,
+    ident: &Ident,
+) -> TokenStream2 {
+    let span = ident.span();
+    if !context.self_attrs.repr_c {
+        return quote_spanned! {span=>
+            compile_error!("unions with #[binary(raw)] must be #[repr(C)] or #[repr(transparent)], so that every member starts at the union's first byte");
+        };
+    }
+    if !generics.params.is_empty() {
+        return quote_spanned! {span=>
+            compile_error!("#[binary(raw)] cannot be used on a generic union, whose members' sizes cannot be checked");
+        };
+    }
+    let mut checks = vec![];
+    for (i, f) in data.fields.named.iter().enumerate() {
+        let ty = &f.ty;
         generics
             .make_where_clause()
             .predicates
             .push(make_generic_bound(
-                if context.self_attrs.flag_value.is_some() {
-                    let ty = &f.ty;
-                    parse_quote! {<#ty as ::binary::DeOption>::Assoc}
-                } else {
-                    f.ty.clone()
-                },
-                parse_quote! {::binary::BinSerialize},
+                ty.clone(),
+                parse_quote! {_binary::RawBytes},
             ));
+        let message = format!(
+            "member `{}` of a #[binary(raw)] union must be as large as the union, or encoding the union after writing it would read uninitialized bytes",
+            field_name(&f.ident, i)
+        );
+        let span = f.span();
+        checks.push(quote_spanned! {span=>
+            const _: () = assert!(
+                ::std::mem::size_of::<#ty>() == ::std::mem::size_of::<#ident>(),
+                #message,
+            );
+        });
+    }
+    quote! { #(#checks)* }
+}
 
-        let ident: TokenStream2 = if context.env == Environment::Enum {
-            let (name, span) = match &f.ident {
-                Some(n) => (format!("self_{}", n), n.span()),
-                None => (format!("self_{}", i), span),
+fn union_tag_error(f: &Field) -> TokenStream2 {
+    let span = f.span();
+    quote_spanned! {span=>
+        compile_error!("members of a union with #[binary(unsafe_select = \"...\")] must have #[binary(tag = ...)]");
+    }
+}
+
+fn pattern_fields(f: &Fields) -> TokenStream2 {
+    match f {
+        Fields::Named(f) => {
+            let mut v = vec![];
+            for f in &f.named {
+                let base_ident = f.ident.clone().unwrap();
+                let ident = Ident::new(
+                    &format!("self_{}", f.ident.clone().unwrap()),
+                    f.ident.span(),
+                );
+                v.push(quote! { #base_ident: #ident });
+            }
+            quote! { { #(#v),* } }
+        }
+        Fields::Unnamed(f) => {
+            let mut v = vec![];
+            for i in 0..f.unnamed.len() {
+                let ident = Ident::new(&format!("self_{}", i), f.unnamed[i].span());
+                v.push(quote! { #ident });
+            }
+            quote! { ( #(#v),* ) }
+        }
+        Fields::Unit => quote! {},
+    }
+}
+
+// Builds the body of the decode function. With `untagged`, an enum's body is that of
+// BinDeserializeTagged::decode_untagged, which takes the tag as `tag` rather than reading it.
+fn decode_type(
+    context: Context,
+    mut generics: Generics,
+    data: Data,
+    ident: &Ident,
+    untagged: bool,
+) -> (Generics, TokenStream2) {
+    match data {
+        Data::Struct(s) => {
+            let (generics, decodes, transfers, errors) =
+                decode_fields(&context, generics, s.fields, None, None);
+            let validate = build_validate(&context, quote! { &value });
+            let decode = build_nest_decode(&context, decodes, quote! { Self #transfers });
+            (
+                generics,
+                quote! {
+                    #errors
+                    let value = #decode;
+                    #validate
+                    value
+                },
+            )
+        }
+        Data::Enum(e) => {
+            let mut variants: Vec<TokenStream2> = vec![];
+
+            let (tags, tag_errors) = resolve_variant_tags(&context, &e.variants, ident);
+            let tags = match tags {
+                Some(t) => t,
+                // Keeps the errors from being followed by a type mismatch
+                None => return (generics, quote! { #tag_errors unreachable!() }),
             };
-            let new_ident = Ident::new(&name, span);
-            quote! { #new_ident }
-        } else {
-            let ident = match &f.ident {
-                Some(i) => Member::Named(i.clone()),
-                _ => Member::Unnamed(Index {
-                    index: i as u32,
-                    span,
-                }),
+            let header = if untagged {
+                quote! {
+                    #tag_errors
+                    let variant = tag;
+                }
+            } else {
+                let ty = &tags.ty;
+                let attrs = helpers::build_tag_attrs(context.self_attrs.tag_le);
+                quote! {
+                    #tag_errors
+                    let variant = <#ty as _binary::BinDeserialize>::decode_from(buf, #attrs)?;
+                }
             };
-            quote! { self.#ident }
+            let mut default_variant = None;
+
+            for (vi, v) in e.variants.into_iter().enumerate() {
+                let (context, attr_errors) =
+                    context.recurse_into(Level::Variant, v.ident.to_string(), &v.attrs);
+                let name = &v.ident;
+                let tag_field = find_tag_field(&context, &v.fields).map(|(i, _)| i);
+                let default_errors = if context.self_attrs.tag_default {
+                    Some(build_default_errors(
+                        &v,
+                        tag_field,
+                        default_variant.is_some(),
+                    ))
+                } else {
+                    None
+                };
+                let pattern = if default_errors.is_some() {
+                    quote! { _ }
+                } else {
+                    tags.build_pattern(vi).unwrap_or_else(|| quote! { _ })
+                };
+                {
+                    let tag_ty = if context.self_attrs.nest {
+                        None
+                    } else {
+                        Some(&tags.ty)
+                    };
+                    let (newgen, decodes, transfers, errors) =
+                        decode_fields(&context, generics, v.fields, tag_field, tag_ty);
+                    generics = newgen;
+
+                    let decode =
+                        build_nest_decode(&context, decodes, quote! { #ident::#name#transfers });
+                    let arm = quote! {
+                        #pattern => {
+                            #attr_errors
+                            #default_errors
+                            #errors
+                            #decode
+                        }
+                    };
+                    if default_errors.is_some() {
+                        default_variant = Some(arm);
+                    } else {
+                        variants.push(arm);
+                    }
+                }
+            }
+            if default_variant.is_none() {
+                default_variant = Some(if tags.bytes {
+                    quote! {
+                        _ => return Err(_binary::BinError::ByteVariantNotMatched(variant.to_vec()))
+                    }
+                } else {
+                    quote! {
+                        _ => return Err(_binary::BinError::VariantNotMatched(variant as u64))
+                    }
+                })
+            }
+            let validate = build_validate(&context, quote! { &value });
+            let consts = tags.build_consts();
+            let scrutinee = tags.build_scrutinee(quote! { variant });
+            let decode = quote! {
+                #header
+                #consts
+                let value = match #scrutinee {
+                    #(#variants)*
+                    #default_variant
+                };
+                #validate
+                value
+            };
+            (generics, decode)
+        }
+        Data::Union(u) => decode_union(&context, generics, u, ident),
+    }
+}
+
+fn decode_union(
+    context: &Context,
+    mut generics: Generics,
+    data: DataUnion,
+    ident: &Ident,
+) -> (Generics, TokenStream2) {
+    if context.self_attrs.raw {
+        let checks = raw_union_checks(context, &mut generics, &data, ident);
+        return (
+            generics,
+            quote! {
+                #checks
+                let mut value = ::std::mem::MaybeUninit::<Self>::zeroed();
+                // Safety: raw_union_checks ensures every member is RawBytes, so any bytes make a
+                // valid value
+                let bytes = unsafe {
+                    ::std::slice::from_raw_parts_mut(
+                        value.as_mut_ptr() as *mut u8,
+                        ::std::mem::size_of::<Self>(),
+                    )
+                };
+                ::std::io::Read::read_exact(buf, bytes)?;
+                unsafe { value.assume_init() }
+            },
+        );
+    }
+    let select = match &context.self_attrs.select {
+        Some(v) => v,
+        None => return (generics, union_selector_error(ident)),
+    };
+
+    let mut members = vec![];
+    for (i, f) in data.fields.named.into_iter().enumerate() {
+        let (context, attr_errors) =
+            context.recurse_into(Level::Field, field_name(&f.ident, i), &f.attrs);
+        let name = &f.ident;
+        let tag = match &context.self_attrs.tag_value {
+            Some(v) => v,
+            None => {
+                members.push(union_tag_error(&f));
+                continue;
+            }
         };
 
+        if let Some(bound) = &context.self_attrs.de_bound {
+            extend_bounds(&mut generics, bound);
+        } else if context.self_attrs.deserialize_with.is_none() && context.self_attrs.map.is_none()
+        {
+            generics
+                .make_where_clause()
+                .predicates
+                .push(make_generic_bound(
+                    match &context.self_attrs.from {
+                        Some((raw, _)) => raw.clone(),
+                        None => f.ty.clone(),
+                    },
+                    deserialize_trait(&context),
+                ));
+        }
+
         let attrs = context.build_attrs();
+        let decode = decode_value(&context, &f.ty, &attrs);
+        members.push(quote! {
+            #tag => {
+                #attr_errors
+                Self { #name: #decode }
+            }
+        });
+    }
 
-        if context.self_attrs.flags {
-            if flags_ty.is_some() {
-                let span = f.span();
-                encodes.push(quote_spanned! {span=>
-                    compile_error!("multiple #[binary(flags)] attributes in this struct");
-                });
+    (
+        generics,
+        quote! {
+            match #select {
+                #(#members)*
+                other => return Err(_binary::BinError::VariantNotMatched(other as u64)),
             }
-            flags_ty = Some(f.ty.clone());
-            let ty = &f.ty;
+        },
+    )
+}
+
+// Builds the statements encoding a struct's or variant's fields, except for `tag_field`, which is
+// written as the variant's tag. Alignment counts from the start of the variant's `tag_ty` tag.
+fn encode_fields(
+    context: &Context,
+    mut generics: Generics,
+    fields: Fields,
+    tag_field: Option<usize>,
+    tag_ty: Option<&Type>,
+) -> (Generics, Vec<TokenStream2>) {
+    let mut encodes = vec![];
+    let fields = match fields {
+        Fields::Named(n) => n.named,
+        Fields::Unnamed(u) => u.unnamed,
+        Fields::Unit => return (generics, vec![]),
+    };
+    let (flag_words, flag_tests, flag_errors) = resolve_flags(context, &fields);
+    encodes.push(flag_errors);
+    let mut needs_position = false;
+    let access = if context.env == Environment::Enum {
+        helpers::FieldAccess::Borrowed
+    } else {
+        helpers::FieldAccess::SelfRef
+    };
+    encodes.push(build_encode_asserts(context, access));
+
+    // Flags words are worked out before anything is written, so that they can come before,
+    // between or after the fields that depend on them
+    for (k, word) in flag_words.iter().enumerate() {
+        let f = &fields[word.field];
+        let ty = &f.ty;
+        let flags = flag_word_ident(k, f.span());
+        let value = encode_field_access(context, f, word.field);
+        let strict = build_flags_strict(word, quote! { &#flags });
+        encodes.push(quote! {
+            let mut #flags: #ty = ::std::clone::Clone::clone(&#value);
+            #strict
+        });
+    }
+    for (i, f) in fields.iter().enumerate() {
+        if let Some((k, test)) = &flag_tests[i] {
+            let value = encode_field_access(context, f, i);
+            let (set, clear) =
+                build_flag_update(&field_name(&f.ident, i), &flag_words[*k], *k, test);
             encodes.push(quote! {
-                let mut flags: #ty = <#ty as ::binary::BinFlags>::ZERO;
-                let mut flagged = vec![];
-                let unflagged_buf = buf;
-                let buf: &mut dyn ::binary::BinWrite = &mut flagged;
+                if ::std::option::Option::is_some(&#value) {
+                    #set
+                } else {
+                    #clear
+                }
             });
-            tail = Some(quote! {
-                <#ty as ::binary::BinSerialize>::encode_to(&flags, unflagged_buf, #attrs)?;
-                <::std::vec::Vec<u8> as ::binary::BinSerialize>::encode_to(&flagged, unflagged_buf, ::binary::attr::Attrs::zero())?;
+        }
+    }
+    let (struct_before, struct_after) =
+        helpers::build_pad_encode(&context.self_attrs, true, &mut needs_position);
+    encodes.push(struct_before);
+    let len = fields.len();
+    for (i, f) in fields.into_iter().enumerate() {
+        let span = f.span();
+        let (context, attr_errors) =
+            context.recurse_into(Level::Field, field_name(&f.ident, i), &f.attrs);
+        if tag_field == Some(i) {
+            encodes.push(attr_errors);
+            continue;
+        }
+        let (before, after) =
+            helpers::build_pad_encode(&context.self_attrs, false, &mut needs_position);
+        encodes.push(before);
+
+        if context.self_attrs.rest {
+            let ident = encode_field_access(&context, &f, i);
+            encodes.push(rest_errors(&context, span, i + 1 == len));
+            encodes.push(quote! {
+                ::std::io::Write::write_all(buf, &#ident)?;
+                #attr_errors
             });
-        } else if let Some(v) = context.self_attrs.flag_value {
-            let set = if flags_ty.is_none() {
-                if !warned_for_no_flags {
-                    warned_for_no_flags = true;
-                    let span = f.span();
-                    encodes.push(quote_spanned! {span=>
-                        compile_error!("no #[binary(flags)] attribute before this #[binary(flags(...))]");
-                    });
-                }
-                None
-            } else {
-                let v = LitInt::new(v, IntSuffix::None, span);
-                Some(quote! { <#flags_ty as ::binary::BinFlags>::set(&mut flags, #v); })
-            };
+            encodes.push(after);
+            continue;
+        }
+
+        if let Some(bound) = &context.self_attrs.ser_bound {
+            extend_bounds(&mut generics, bound);
+        } else if context.self_attrs.serialize_with.is_none() {
+            generics
+                .make_where_clause()
+                .predicates
+                .push(make_generic_bound(
+                    match &context.self_attrs.into {
+                        Some((raw, _)) => raw.clone(),
+                        None => value_type(&context, &f.ty),
+                    },
+                    if context.self_attrs.args.is_some() {
+                        parse_quote! {_binary::BinSerializeArgs}
+                    } else if context.self_attrs.tag_expr.is_some() {
+                        parse_quote! {_binary::BinSerializeTagged}
+                    } else {
+                        parse_quote! {_binary::BinSerialize}
+                    },
+                ));
+        }
+        if context.self_attrs.map.is_some()
+            && context.self_attrs.into.is_none()
+            && context.self_attrs.serialize_with.is_none()
+        {
+            encodes.push(quote_spanned! {span=>
+                compile_error!("a field with #[binary(map = ...)] or #[binary(try_map = ...)] needs #[binary(into = ...)] or #[binary(serialize_with = ...)] to be serialized");
+            });
+        }
+
+        let ident = encode_field_access(&context, &f, i);
+
+        let attrs = context.build_attrs();
+
+        encodes.push(build_encode_asserts(&context, access));
+        encodes.push(build_encode_validate(&context, quote! { &#ident }));
+
+        if let Some(k) = flag_words.iter().position(|w| w.field == i) {
+            let flags = flag_word_ident(k, span);
+            let encode = encode_value(&context, quote! { &#flags }, &attrs);
+            encodes.push(quote! {
+                #encode
+                #attr_errors
+            });
+        } else if context.self_attrs.flag.is_some() {
+            let encode = encode_value(&context, quote! { v }, &attrs);
             encodes.push(quote! {
                 if let Some(v) = &#ident {
-                    #set
-                    ::binary::BinSerialize::encode_to(v, buf, #attrs)?;
+                    #encode
+                }
+                #attr_errors
+            });
+        } else if let Some(cond) = &context.self_attrs.condition {
+            let cond = helpers::rewrite_field_refs(cond.clone(), access);
+            let name = &context.name;
+            let encode = encode_value(&context, quote! { v }, &attrs);
+            encodes.push(quote! {
+                match ((#cond), &#ident) {
+                    (true, Some(v)) => {
+                        #encode
+                    }
+                    (false, None) => {}
+                    _ => return Err(_binary::BinError::ConditionMismatch(#name.to_string())),
                 }
+                #attr_errors
             });
         } else {
+            let encode = encode_value(&context, quote! { &#ident }, &attrs);
             encodes.push(quote! {
-                ::binary::BinSerialize::encode_to(&#ident, buf, #attrs)?;
+                #encode
                 #attr_errors
             });
         }
+        encodes.push(after);
     }
-    if let Some(tail) = tail {
-        encodes.push(tail);
+
+    encodes.push(struct_after);
+    if needs_position {
+        encodes.insert(0, build_counted(tag_ty));
     }
     (generics, encodes)
 }
 
+// Wraps `buf` to count the position of the fields, from the start of the tag before them if any
+fn build_counted(tag_ty: Option<&Type>) -> TokenStream2 {
+    match tag_ty {
+        Some(ty) => quote! {
+            let buf = &mut _binary::Counted::starting_at(
+                buf,
+                <#ty as _binary::BinSize>::MIN_SIZE as u64,
+            );
+        },
+        None => quote! {
+            let buf = &mut _binary::Counted::new(buf);
+        },
+    }
+}
+
+// A #[binary(flags)] word, with the bits claimed by the fields whose presence it decides
+struct FlagWord {
+    field: usize,
+    name: String,
+    ty: Type,
+    strict: bool,
+    claimed: u64,
+}
+
+// The index of the flags word a field's presence depends on, and the test it applies to it
+type FlagDependency = (usize, helpers::FlagTest);
+
+// Finds the flags words among a struct's or variant's fields, and the word each flags(...) field
+// tests as an index into them. A test reads the nearest word before its field, or the first
+// after it if there is none before, unless it names another with word = "...".
+fn resolve_flags(
+    context: &Context,
+    fields: &Punctuated<Field, Token![,]>,
+) -> (Vec<FlagWord>, Vec<Option<FlagDependency>>, TokenStream2) {
+    let field_attrs: Vec<_> = fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| {
+            let (context, _) =
+                context.recurse_into(Level::Field, field_name(&f.ident, i), &f.attrs);
+            context.self_attrs
+        })
+        .collect();
+
+    let mut words = vec![];
+    for (i, (f, attrs)) in fields.iter().zip(&field_attrs).enumerate() {
+        if attrs.flags {
+            words.push(FlagWord {
+                field: i,
+                name: field_name(&f.ident, i),
+                ty: f.ty.clone(),
+                strict: attrs.flags_strict,
+                claimed: 0,
+            });
+        }
+    }
+
+    let mut tests: Vec<Option<FlagDependency>> = vec![];
+    let mut errors = vec![];
+    for (i, (f, attrs)) in fields.iter().zip(&field_attrs).enumerate() {
+        let test = match &attrs.flag {
+            Some(t) => t,
+            None => {
+                tests.push(None);
+                continue;
+            }
+        };
+        let span = f.span();
+        let word = match &test.word {
+            Some(name) => words.iter().position(|w| &w.name == name),
+            None => words
+                .iter()
+                .rposition(|w| w.field < i)
+                .or_else(|| words.iter().position(|w| w.field > i)),
+        };
+        match word {
+            Some(k) => {
+                // Two tests of the same bits must want different values of them, or both
+                // fields would be present whenever one is
+                let conflict = tests.iter().enumerate().find_map(|(j, other)| match other {
+                    Some((other_k, other)) if *other_k == k => {
+                        let shared = test.mask & other.mask;
+                        let bits = |t: &helpers::FlagTest| t.eq.unwrap_or(t.mask) & shared;
+                        if shared != 0 && bits(test) == bits(other) {
+                            Some((j, shared))
+                        } else {
+                            None
+                        }
+                    }
+                    _ => None,
+                });
+                if let Some((j, shared)) = conflict {
+                    let message = format!(
+                        "flag bits {:#x} are already tested by field `{}`",
+                        shared,
+                        field_name(&fields[j].ident, j)
+                    );
+                    errors.push(quote_spanned! {span=>
+                        compile_error!(#message);
+                    });
+                    let other_span = fields[j].span();
+                    let message = format!(
+                        "flag bits {:#x} are tested again by field `{}`",
+                        shared,
+                        field_name(&f.ident, i)
+                    );
+                    errors.push(quote_spanned! {other_span=>
+                        compile_error!(#message);
+                    });
+                }
+                words[k].claimed |= test.mask;
+            }
+            None if test.word.is_some() => {
+                errors.push(quote_spanned! {span=>
+                    compile_error!("no #[binary(flags)] field of this name");
+                });
+            }
+            None => {
+                errors.push(quote_spanned! {span=>
+                    compile_error!("no #[binary(flags)] attribute for this #[binary(flags(...))]");
+                });
+            }
+        }
+        tests.push(word.map(|k| (k, test.clone())));
+    }
+
+    (words, tests, quote! { #(#errors)* })
+}
+
+// The local holding the value of the k'th flags word while encoding
+fn flag_word_ident(k: usize, span: proc_macro2::Span) -> Ident {
+    Ident::new(&format!("flags_{}", k), span)
+}
+
+// Checks that a strict flags word, behind the reference `value`, has no unclaimed bits set
+fn build_flags_strict(word: &FlagWord, value: TokenStream2) -> TokenStream2 {
+    if !word.strict {
+        return quote! {};
+    }
+    let ty = &word.ty;
+    let claimed = LitInt::new(
+        word.claimed,
+        IntSuffix::None,
+        proc_macro2::Span::call_site(),
+    );
+    quote! {
+        {
+            let unclaimed = <#ty as _binary::BinFlags>::bits(#value) & !#claimed;
+            if unclaimed != 0 {
+                return Err(_binary::BinError::UnknownFlags(unclaimed));
+            }
+        }
+    }
+}
+
+// Whether the flags word behind the reference `value` says a field with this test is present
+fn build_flag_test(word: &FlagWord, value: TokenStream2, test: &helpers::FlagTest) -> TokenStream2 {
+    let ty = &word.ty;
+    let span = proc_macro2::Span::call_site();
+    let mask = LitInt::new(test.mask, IntSuffix::None, span);
+    match test.eq {
+        Some(eq) => {
+            let eq = LitInt::new(eq, IntSuffix::None, span);
+            quote! { <#ty as _binary::BinFlags>::matches(#value, #mask, #eq) }
+        }
+        None => quote! { <#ty as _binary::BinFlags>::has(#value, #mask) },
+    }
+}
+
+// Updates the k'th flags word for a field with this test being present or absent. A field
+// with a nonzero eq is marked absent by clearing its mask; one with eq = 0 cannot be, so
+// encoding fails if other fields have not already given the masked bits another value.
+fn build_flag_update(
+    name: &str,
+    word: &FlagWord,
+    k: usize,
+    test: &helpers::FlagTest,
+) -> (TokenStream2, TokenStream2) {
+    let ty = &word.ty;
+    let span = proc_macro2::Span::call_site();
+    let flags = flag_word_ident(k, span);
+    let mask = LitInt::new(test.mask, IntSuffix::None, span);
+    match test.eq {
+        None => (
+            quote! { <#ty as _binary::BinFlags>::set(&mut #flags, #mask); },
+            quote! { <#ty as _binary::BinFlags>::clear(&mut #flags, #mask); },
+        ),
+        Some(eq) => {
+            let test = build_flag_test(word, quote! { &#flags }, test);
+            let clear = if eq != 0 {
+                quote! { <#ty as _binary::BinFlags>::set_bits(&mut #flags, #mask, 0); }
+            } else {
+                quote! { return Err(_binary::BinError::ConditionMismatch(#name.to_string())); }
+            };
+            let eq = LitInt::new(eq, IntSuffix::None, span);
+            (
+                quote! { <#ty as _binary::BinFlags>::set_bits(&mut #flags, #mask, #eq); },
+                quote! {
+                    if #test {
+                        #clear
+                    }
+                },
+            )
+        }
+    }
+}
+
+// The expression for a field of the value being encoded: a binding to it for enum variants,
+// which are matched by reference, or a place expression for structs
+fn encode_field_access(context: &Context, f: &Field, i: usize) -> TokenStream2 {
+    let span = f.span();
+    if context.env == Environment::Enum {
+        let (name, span) = match &f.ident {
+            Some(n) => (format!("self_{}", n), n.span()),
+            None => (format!("self_{}", i), span),
+        };
+        let new_ident = Ident::new(&name, span);
+        quote! { (*#new_ident) }
+    } else {
+        let ident = match &f.ident {
+            Some(i) => Member::Named(i.clone()),
+            _ => Member::Unnamed(Index {
+                index: i as u32,
+                span,
+            }),
+        };
+        quote! { self.#ident }
+    }
+}
+
+// The name of a field as shown in errors: its identifier, or its index for tuple fields
+fn field_name(ident: &Option<Ident>, index: usize) -> String {
+    match ident {
+        Some(i) => i.to_string(),
+        None => index.to_string(),
+    }
+}
+
+// Checks the assertions given at this level, reporting failures against the context's name
+fn build_asserts(context: &Context, access: helpers::FieldAccess) -> TokenStream2 {
+    let name = &context.name;
+    let checks = context.self_attrs.asserts.iter().map(|(expr, message)| {
+        let expr = helpers::rewrite_field_refs(expr.clone(), access);
+        quote! {
+            if !(#expr) {
+                return Err(_binary::BinError::ValidationFailed {
+                    field: #name.to_string(),
+                    message: #message.to_string(),
+                });
+            }
+        }
+    });
+    quote! { #(#checks)* }
+}
+
+// Runs the validation hook given at this level, if any, on the value behind the reference `value`
+fn build_validate(context: &Context, value: TokenStream2) -> TokenStream2 {
+    match &context.self_attrs.validate {
+        Some(path) => {
+            let name = &context.name;
+            quote! {
+                if let Err(e) = #path(#value) {
+                    return Err(_binary::BinError::ValidationFailed {
+                        field: #name.to_string(),
+                        message: e.to_string(),
+                    });
+                }
+            }
+        }
+        None => quote! {},
+    }
+}
+
+// As build_asserts, for encoding; only emitted when the checks were requested with check_encode
+fn build_encode_asserts(context: &Context, access: helpers::FieldAccess) -> TokenStream2 {
+    if context.self_attrs.check_encode {
+        build_asserts(context, access)
+    } else {
+        quote! {}
+    }
+}
+
+// As build_validate, for encoding; only emitted when the checks were requested with check_encode
+fn build_encode_validate(context: &Context, value: TokenStream2) -> TokenStream2 {
+    if context.self_attrs.check_encode {
+        build_validate(context, value)
+    } else {
+        quote! {}
+    }
+}
+
+// The type of the value a field holds; for optional fields this is the type inside the Option
+fn value_type(context: &Context, ty: &Type) -> Type {
+    if context.self_attrs.is_optional() {
+        parse_quote! {<#ty as _binary::DeOption>::Assoc}
+    } else {
+        ty.clone()
+    }
+}
+
+// Wraps a conversion error for the field in the context as a BinError
+fn conversion_error(context: &Context) -> TokenStream2 {
+    let name = &context.name;
+    quote! {
+        |e| _binary::BinError::ConversionFailed {
+            field: #name.to_string(),
+            message: e.to_string(),
+        }
+    }
+}
+
+// Encodes the value behind the reference `value`, honouring any conversion and custom serializer
+// on the field
+fn encode_value(context: &Context, value: TokenStream2, attrs: &TokenStream2) -> TokenStream2 {
+    let value = match &context.self_attrs.into {
+        Some((raw, false)) => quote! {
+            &::std::convert::Into::<#raw>::into((*#value).clone())
+        },
+        Some((raw, true)) => {
+            let err = conversion_error(context);
+            quote! {
+                &::std::convert::TryInto::<#raw>::try_into((*#value).clone()).map_err(#err)?
+            }
+        }
+        None => value,
+    };
+    let access = if context.env == Environment::Enum {
+        helpers::FieldAccess::Borrowed
+    } else {
+        helpers::FieldAccess::SelfRef
+    };
+    let encode = match (
+        &context.self_attrs.serialize_with,
+        &context.self_attrs.args,
+        &context.self_attrs.tag_expr,
+    ) {
+        (Some(path), _, _) => quote! {
+            #path(#value, buf, #attrs)?;
+        },
+        (None, Some(args), None) => {
+            let args = helpers::rewrite_field_refs(args.clone(), access);
+            quote! {
+                _binary::BinSerializeArgs::encode_with(#value, buf, #attrs, (#args,))?;
+            }
+        }
+        (None, None, Some(tag)) => {
+            let tag = helpers::rewrite_field_refs(tag.clone(), access);
+            quote! {
+                _binary::BinSerializeTagged::encode_untagged(#value, buf, #attrs, &(#tag))?;
+            }
+        }
+        (None, Some(_), Some(_)) => tag_args_error(context),
+        (None, None, None) => quote! {
+            _binary::BinSerialize::encode_to(#value, buf, #attrs)?;
+        },
+    };
+    build_nest_encode(&context.self_attrs, encode)
+}
+
+// Reports a #[binary(rest)] field that is not last, or that is (de)serialized in some other way
+fn rest_errors(context: &Context, span: proc_macro2::Span, last: bool) -> TokenStream2 {
+    let self_attrs = &context.self_attrs;
+    if !last {
+        quote_spanned! {span=>
+            compile_error!("#[binary(rest)] must be on the last field");
+        }
+    } else if self_attrs.is_optional()
+        || self_attrs.nest
+        || self_attrs.serialize_with.is_some()
+        || self_attrs.deserialize_with.is_some()
+        || self_attrs.args.is_some()
+        || self_attrs.tag_expr.is_some()
+        || self_attrs.map.is_some()
+        || self_attrs.from.is_some()
+        || self_attrs.into.is_some()
+    {
+        quote_spanned! {span=>
+            compile_error!("#[binary(rest)] cannot be combined with attributes changing how a field is (de)serialized");
+        }
+    } else {
+        quote! {}
+    }
+}
+
+// Makes `encodes` write into a buffer of their own, which is then written with a length prefix
+fn build_nest_encode(self_attrs: &SelfAttrs, encodes: TokenStream2) -> TokenStream2 {
+    if !self_attrs.nest {
+        return encodes;
+    }
+    let attrs = helpers::build_nest_attrs(self_attrs.nest_le, self_attrs.nest_ty.unwrap());
+    // The buffer's contents will follow its length prefix in the stream
+    let prefix = self_attrs.nest_ty.unwrap().size() as u64;
+    quote! {
+        let nested = {
+            let mut nested = ::std::vec::Vec::<u8>::new();
+            {
+                let _level = _binary::StreamLevel::enter(_binary::stream_position().map(|p| p + #prefix));
+                let buf: &mut dyn _binary::BinWrite = &mut _binary::Tracked::new(&mut nested);
+                #encodes
+            }
+            nested
+        };
+        _binary::BinSerialize::encode_to(&nested, buf, #attrs)?;
+    }
+}
+
+// The expression running `decodes` and evaluating to `value`, within a length-prefixed buffer that
+// must be used up if nested
+fn build_nest_decode(
+    context: &Context,
+    decodes: TokenStream2,
+    value: TokenStream2,
+) -> TokenStream2 {
+    let self_attrs = &context.self_attrs;
+    if !self_attrs.nest {
+        return quote! {
+            {
+                #decodes
+                #value
+            }
+        };
+    }
+    let attrs = helpers::build_nest_attrs(self_attrs.nest_le, self_attrs.nest_ty.unwrap());
+    if context.borrowed {
+        return quote! {
+            {
+                let mut nested = <&'de [u8] as _binary::BinDeserializeBorrowed<'de>>::decode_borrowed(buf, #attrs)?;
+                let value = {
+                    let _level = _binary::StreamLevel::enter(
+                        _binary::stream_position().map(|end| end - nested.len() as u64),
+                    );
+                    let buf: &mut dyn _binary::BorrowRead<'de> = &mut _binary::Tracked::new(&mut nested);
+                    #decodes
+                    #value
+                };
+                if !nested.is_empty() {
+                    return Err(_binary::BinError::TrailingData(nested.len() as u64));
+                }
+                value
+            }
+        };
+    }
+    quote! {
+        {
+            let nested = <::std::vec::Vec<u8> as _binary::BinDeserialize>::decode_from(buf, #attrs)?;
+            let mut nested = nested.as_slice();
+            let value = {
+                // The buffer has been read, so it ended at the current position in the stream
+                let _level = _binary::StreamLevel::enter(
+                    _binary::stream_position().map(|end| end - nested.len() as u64),
+                );
+                let buf: &mut dyn _binary::BinRead = &mut _binary::Tracked::new(&mut nested);
+                #decodes
+                #value
+            };
+            if !nested.is_empty() {
+                return Err(_binary::BinError::TrailingData(nested.len() as u64));
+            }
+            value
+        }
+    }
+}
+
+fn tag_args_error(context: &Context) -> TokenStream2 {
+    let span = context.attrs.span();
+    quote_spanned! {span=>
+        compile_error!("#[binary(tag = ...)] cannot be combined with #[binary(args = ...)]")
+    }
+}
+
+// Decodes a value of type `ty`, honouring any custom deserializer and conversion on the field
+fn decode_value(context: &Context, ty: &Type, attrs: &TokenStream2) -> TokenStream2 {
+    let self_attrs = &context.self_attrs;
+    let raw_ty = match (&self_attrs.from, &self_attrs.map) {
+        (Some((raw, _)), _) => raw.clone(),
+        (None, Some(_)) => parse_quote! { _ },
+        (None, None) => ty.clone(),
+    };
+    let raw = match (
+        &self_attrs.deserialize_with,
+        &self_attrs.args,
+        &self_attrs.tag_expr,
+    ) {
+        (Some(path), _, _) => quote! {
+            #path(buf, #attrs)?
+        },
+        (None, Some(args), None) => {
+            let args = helpers::rewrite_field_refs(args.clone(), helpers::FieldAccess::Owned);
+            quote! {
+                <#raw_ty as _binary::BinDeserializeArgs>::decode_with(buf, #attrs, (#args,))?
+            }
+        }
+        (None, None, Some(tag)) => {
+            let tag = helpers::rewrite_field_refs(tag.clone(), helpers::FieldAccess::Owned);
+            quote! {
+                <#raw_ty as _binary::BinDeserializeTagged>::decode_untagged(buf, #attrs, #tag)?
+            }
+        }
+        (None, Some(_), Some(_)) => tag_args_error(context),
+        (None, None, None) if context.borrowed => quote! {
+            <#raw_ty as _binary::BinDeserializeBorrowed<'de>>::decode_borrowed(buf, #attrs)?
+        },
+        (None, None, None) => quote! {
+            <#raw_ty as _binary::BinDeserialize>::decode_from(buf, #attrs)?
+        },
+    };
+    let err = conversion_error(context);
+    let decode = match (&self_attrs.from, &self_attrs.map) {
+        (Some((raw_ty, false)), _) => quote! {
+            <#ty as ::std::convert::From<#raw_ty>>::from(#raw)
+        },
+        (Some((raw_ty, true)), _) => quote! {
+            <#ty as ::std::convert::TryFrom<#raw_ty>>::try_from(#raw).map_err(#err)?
+        },
+        (None, Some((path, false))) => quote! {
+            #path(#raw)
+        },
+        (None, Some((path, true))) => quote! {
+            #path(#raw).map_err(#err)?
+        },
+        (None, None) => raw,
+    };
+    if self_attrs.nest {
+        build_nest_decode(context, quote! {}, decode)
+    } else {
+        decode
+    }
+}
+
+// The trait a field's type must implement to be decoded as part of its type
+fn deserialize_trait(context: &Context) -> Path {
+    if context.borrowed {
+        parse_quote! {_binary::BinDeserializeBorrowed<'de>}
+    } else {
+        parse_quote! {_binary::BinDeserialize}
+    }
+}
+
+// Builds the statements decoding a struct's or variant's fields, except for `tag_field`, which is
+// taken from the variant's tag. Alignment counts from the start of the variant's `tag_ty` tag.
 fn decode_fields(
     context: &Context,
     mut generics: Generics,
     fields: Fields,
+    tag_field: Option<usize>,
+    tag_ty: Option<&Type>,
 ) -> (Generics, TokenStream2, TokenStream2, TokenStream2) {
     let mut decodes: Vec<TokenStream2> = vec![];
     let mut transfers: Vec<TokenStream2> = vec![];
@@ -470,25 +1974,81 @@ fn decode_fields(
         Fields::Unnamed(u) => &u.unnamed,
         Fields::Unit => return (generics, quote! {}, quote! {}, quote! {}),
     };
-    let mut flags_ty = None;
-    let mut flags_field = None;
-    let mut warned_for_no_flags = false;
+    let (flag_words, flag_tests, flag_errors) = resolve_flags(context, fields_list);
+    errors.push(flag_errors);
+    // A flags word after fields depending on it is read first, from the end of the struct's or
+    // variant's nested buffer, so it must be the last field; the fields are then read from what
+    // comes before it
+    let mut trailing = None;
+    for (k, word) in flag_words.iter().enumerate() {
+        let depended_on = flag_tests
+            .iter()
+            .enumerate()
+            .any(|(i, t)| matches!(t, Some((t, _)) if *t == k && i < word.field));
+        if !depended_on {
+            continue;
+        }
+        if word.field + 1 == fields_list.len() {
+            trailing = Some(k);
+        } else {
+            let span = fields_list[word.field].span();
+            errors.push(quote_spanned! {span=>
+                compile_error!("a #[binary(flags)] field after fields that depend on it must be the last field to be deserialized");
+            });
+        }
+    }
+    if let (Some(k), false) = (trailing, context.self_attrs.nest) {
+        let span = fields_list[flag_words[k].field].span();
+        errors.push(quote_spanned! {span=>
+            compile_error!("a #[binary(flags)] field after fields that depend on it must be in a nested struct or variant, such as with #[binary(nest(u16))], to be read from the end of its buffer");
+        });
+    }
+    if trailing.is_some()
+        && (context.self_attrs.pad_after.is_some() || context.self_attrs.align.is_some())
+    {
+        let span = fields_list.span();
+        errors.push(quote_spanned! {span=>
+            compile_error!("a #[binary(flags)] field after fields that depend on it cannot be followed by padding");
+        });
+    }
+    let nested = context.self_attrs.nest;
+    let mut flags_split = None;
+    let mut needs_position = false;
+    let (struct_before, struct_after) =
+        helpers::build_pad_decode(&context.self_attrs, true, &mut needs_position);
+    decodes.push(struct_before);
     for (i, f) in fields_list.into_iter().enumerate() {
         let ty = f.ty.clone();
-        let (context, attr_errors) = context.recurse_into(Level::Field, &f.attrs);
+        let (context, attr_errors) =
+            context.recurse_into(Level::Field, field_name(&f.ident, i), &f.attrs);
+        let (before, after) =
+            helpers::build_pad_decode(&context.self_attrs, false, &mut needs_position);
+        decodes.push(before);
 
-        generics
-            .make_where_clause()
-            .predicates
-            .push(make_generic_bound(
-                if context.self_attrs.flag_value.is_some() {
-                    let ty = &f.ty;
-                    parse_quote! {<#ty as ::binary::DeOption>::Assoc}
-                } else {
-                    f.ty.clone()
-                },
-                parse_quote! {::binary::BinDeserialize},
-            ));
+        if let Some(bound) = &context.self_attrs.de_bound {
+            extend_bounds(&mut generics, bound);
+        } else if context.self_attrs.deserialize_with.is_none()
+            && context.self_attrs.map.is_none()
+            && !context.self_attrs.rest
+            && tag_field != Some(i)
+        {
+            generics
+                .make_where_clause()
+                .predicates
+                .push(make_generic_bound(
+                    match &context.self_attrs.from {
+                        Some((raw, _)) => raw.clone(),
+                        None => value_type(&context, &f.ty),
+                    },
+                    if context.self_attrs.args.is_some() {
+                        parse_quote! {_binary::BinDeserializeArgs}
+                    } else if context.self_attrs.tag_expr.is_some() {
+                        parse_quote! {_binary::BinDeserializeTagged}
+                    } else {
+                        deserialize_trait(&context)
+                    },
+                ));
+        }
 
         let struct_ident = &f.ident;
         let colon = if struct_ident.is_some() {
@@ -507,48 +2067,142 @@ fn decode_fields(
         };
 
         let attrs = context.build_attrs();
-        if let Some(v) = context.self_attrs.flag_value {
-            let span = ident.span();
-            let has = if flags_ty.is_none() {
-                if !warned_for_no_flags {
-                    warned_for_no_flags = true;
-                    let span = f.span();
-                    decodes.push(quote_spanned! {span=>
-                        compile_error!("no #[binary(flags)] attribute before this #[binary(flags(...))]");
-                    });
+        if tag_field == Some(i) {
+            decodes.push(quote! {
+                let #ident = variant;
+            });
+        } else if context.self_attrs.rest {
+            decodes.push(rest_errors(&context, f.span(), i + 1 == fields_list.len()));
+            decodes.push(if context.borrowed {
+                // Either borrowed as it is, or copied into an owned buffer
+                quote! {
+                    let #ident = ::std::convert::From::from(_binary::BorrowRead::take_rest(buf));
                 }
-                quote! { false }
             } else {
-                let v = LitInt::new(v, IntSuffix::None, span);
-                quote! { <#flags_ty as ::binary::BinFlags>::has(&#flags_field, #v) }
+                quote! {
+                    let #ident = _binary::BinRead::read_rest(buf)?;
+                }
+            });
+        } else if trailing.map(|k| flag_words[k].field) == Some(i) {
+            let self_attrs = &context.self_attrs;
+            if self_attrs.pad_before.is_some()
+                || self_attrs.pad_after.is_some()
+                || self_attrs.align.is_some()
+            {
+                let span = f.span();
+                decodes.push(quote_spanned! {span=>
+                    compile_error!("a #[binary(flags)] field after fields that depend on it cannot be padded");
+                });
+            }
+            generics
+                .make_where_clause()
+                .predicates
+                .push(make_generic_bound(
+                    ty.clone(),
+                    parse_quote! {_binary::BinSize},
+                ));
+            let decode = decode_value(&context, &ty, &attrs);
+            let buf_ty = if context.borrowed {
+                quote! { _binary::BorrowRead<'de> }
+            } else {
+                quote! { _binary::BinRead }
+            };
+            if nested {
+                // Splits the word off the end of the `nested` buffer build_nest_decode reads the
+                // fields from, leaving it used up; `buf` is not used again once shadowed
+                flags_split = Some(quote! {
+                    let flags_size = const {
+                        match <#ty as _binary::BinSize>::FIXED_SIZE {
+                            Some(size) => size,
+                            None => panic!("a #[binary(flags)] field after fields that depend on it must have a fixed size"),
+                        }
+                    };
+                    if nested.len() < flags_size {
+                        return Err(_binary::BinError::InsufficientData);
+                    }
+                    let (mut flags_head, mut flags_tail) = nested.split_at(nested.len() - flags_size);
+                    nested = &nested[nested.len()..];
+                    let #ident = {
+                        let buf: &mut dyn #buf_ty = &mut flags_tail;
+                        #decode
+                    };
+                    let buf: &mut dyn #buf_ty = &mut _binary::Tracked::new(&mut flags_head);
+                });
+                decodes.push(quote! {
+                    if !flags_head.is_empty() {
+                        return Err(_binary::BinError::TrailingData(flags_head.len() as u64));
+                    }
+                });
+            } else {
+                // Reported above; decoded in place to report nothing more
+                decodes.push(quote! {
+                    let #ident = #decode;
+                });
+            }
+        } else if context.self_attrs.flag.is_some() {
+            let has = match &flag_tests[i] {
+                Some((k, _)) if flag_words[*k].field > i && (trailing != Some(*k) || !nested) => {
+                    quote! { false }
+                }
+                Some((k, test)) => {
+                    let word = &flag_words[*k];
+                    let value = Ident::new(&format!("self_{}", word.name), ident.span());
+                    build_flag_test(word, quote! { &#value }, test)
+                }
+                None => quote! { false },
             };
+            let decode = decode_value(
+                &context,
+                &parse_quote! { <#ty as _binary::DeOption>::Assoc },
+                &attrs,
+            );
             decodes.push(quote! {
                 let #ident = if #has {
-                    Some(<<#ty as ::binary::DeOption>::Assoc as ::binary::BinDeserialize>::decode_from(buf, #attrs)?)
+                    Some(#decode)
+                } else {
+                    None
+                };
+            });
+        } else if let Some(cond) = &context.self_attrs.condition {
+            let cond = helpers::rewrite_field_refs(cond.clone(), helpers::FieldAccess::Owned);
+            let decode = decode_value(
+                &context,
+                &parse_quote! { <#ty as _binary::DeOption>::Assoc },
+                &attrs,
+            );
+            decodes.push(quote! {
+                let #ident = if #cond {
+                    Some(#decode)
                 } else {
                     None
                 };
             });
         } else {
+            let decode = decode_value(&context, &ty, &attrs);
             decodes.push(quote! {
-                let #ident = <#ty as ::binary::BinDeserialize>::decode_from(buf, #attrs)?;
+                let #ident = #decode;
             });
         }
+        decodes.push(build_asserts(&context, helpers::FieldAccess::Owned));
+        decodes.push(build_validate(&context, quote! { &#ident }));
+        decodes.push(after);
         transfers.push(quote! {
             #struct_ident#colon #ident,
         });
-        if context.self_attrs.flags {
-            if flags_ty.is_some() {
-                let span = f.span();
-                decodes.push(quote_spanned! {span=>
-                    compile_error!("multiple #[binary(flags)] attributes in this struct");
-                });
-            }
-            flags_ty = Some(f.ty.clone());
-            flags_field = Some(ident);
+        if let Some(word) = flag_words.iter().find(|w| w.field == i) {
+            decodes.push(build_flags_strict(word, quote! { &#ident }));
         }
         errors.push(attr_errors);
     }
+    decodes.push(struct_after);
+    decodes.push(build_asserts(context, helpers::FieldAccess::Owned));
+    if needs_position {
+        decodes.insert(0, build_counted(tag_ty));
+    }
+    // Before any Counted wraps the buffer, so that it counts from the start of the nested buffer
+    if let Some(split) = flags_split {
+        decodes.insert(0, split);
+    }
     let errors = quote! { #(#errors)* };
     match fields {
         Fields::Named(_) => (
@@ -0,0 +1,262 @@
+use syn::export::TokenStream2;
+use syn::{Data, DeriveInput, Field, Fields, Generics, Type};
+
+use crate::context::{Context, Level};
+use crate::SelfAttrs;
+
+// Builds the BinSize impl of a type, composing the bounds of its fields with the tags, lengths and
+// padding written around them. Fields (de)serialized in ways the derive cannot see into, such as
+// through serialize_with, may be of any size.
+pub(crate) fn derive(input: DeriveInput) -> TokenStream2 {
+    let (context, attr_errors) = Context::from_input(&input);
+    let ident = &input.ident;
+    let mut generics = input.generics.clone();
+    let mut errors = vec![attr_errors];
+
+    let bounds = match &input.data {
+        Data::Struct(s) => {
+            let start = quote! { _binary::SizeBounds::exact(0) };
+            let fields =
+                fields_bounds(&context, &mut generics, &s.fields, None, start, &mut errors);
+            build_nest(&context.self_attrs, fields)
+        }
+        Data::Enum(e) => {
+            let (tags, tag_errors) = crate::resolve_variant_tags(&context, &e.variants, ident);
+            errors.push(tag_errors);
+            match tags {
+                Some(tags) => {
+                    let ty = &tags.ty;
+                    let tag = quote! { _binary::SizeBounds::of::<#ty>(0) };
+                    let mut variants = vec![];
+                    for v in &e.variants {
+                        let (context, attr_errors) =
+                            context.recurse_into(Level::Variant, v.ident.to_string(), &v.attrs);
+                        errors.push(attr_errors);
+                        let tag_field = crate::find_tag_field(&context, &v.fields).map(|(i, _)| i);
+                        // Fields are aligned from the start of the tag, or of a nested buffer
+                        let nest = context.self_attrs.nest;
+                        let start = if nest {
+                            quote! { _binary::SizeBounds::exact(0) }
+                        } else {
+                            tag.clone()
+                        };
+                        let fields = fields_bounds(
+                            &context,
+                            &mut generics,
+                            &v.fields,
+                            tag_field,
+                            start,
+                            &mut errors,
+                        );
+                        variants.push(if nest {
+                            let fields = build_nest(&context.self_attrs, fields);
+                            quote! { #tag.then(#fields) }
+                        } else {
+                            fields
+                        });
+                    }
+                    variants
+                        .into_iter()
+                        .fold(None, |acc, v| match acc {
+                            Some(acc) => Some(quote! { #acc.or(#v) }),
+                            None => Some(v),
+                        })
+                        .unwrap_or(tag)
+                }
+                None => quote! { _binary::SizeBounds::ANY },
+            }
+        }
+        Data::Union(u) => {
+            if context.self_attrs.raw {
+                quote! { _binary::SizeBounds::exact(::std::mem::size_of::<Self>()) }
+            } else {
+                // The selector is not part of the union's encoding, so only its members are
+                let members = u.fields.named.iter().enumerate().map(|(i, f)| {
+                    let (context, attr_errors) = context.recurse_into(
+                        Level::Field,
+                        crate::field_name(&f.ident, i),
+                        &f.attrs,
+                    );
+                    errors.push(attr_errors);
+                    value_bounds(&context, &mut generics, f)
+                });
+                members
+                    .fold(None, |acc, m| match acc {
+                        Some(acc) => Some(quote! { #acc.or(#m) }),
+                        None => Some(m),
+                    })
+                    .unwrap_or_else(|| quote! { _binary::SizeBounds::exact(0) })
+            }
+        }
+    };
+
+    let mut check = quote! {};
+    if let Some((size, span)) = context.self_attrs.size {
+        let size = size as usize;
+        if input.generics.params.is_empty() {
+            let message = format!(
+                "{} does not have a fixed size, so cannot be checked against `size = {}`",
+                ident, size
+            );
+            check = quote_spanned! {span=>
+                const _: () = assert!(
+                    <#ident as _binary::BinSize>::FIXED_SIZE.is_some(),
+                    #message,
+                );
+                const _: [(); #size] = [(); match <#ident as _binary::BinSize>::FIXED_SIZE {
+                    Some(size) => size,
+                    None => #size,
+                }];
+            };
+        } else {
+            errors.push(quote_spanned! {span=>
+                compile_error!("`size = ...` cannot be checked on a generic type, whose size depends on its parameters");
+            });
+        }
+    }
+
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let imp = quote! {
+        impl#impl_generics _binary::BinSize for #ident#ty_generics #where_clause {
+            const MIN_SIZE: usize = (#bounds).min;
+            const MAX_SIZE: Option<usize> = (#bounds).max;
+        }
+        #check
+    };
+    crate::wrap_impl(&context.self_attrs.krate, imp, quote! { #(#errors)* })
+}
+
+// The bounds of a struct's or variant's fields and the padding around them, following the `start`
+// bounds their alignment counts from, leaving out its tag field, which is written as the tag
+fn fields_bounds(
+    context: &Context,
+    generics: &mut Generics,
+    fields: &Fields,
+    tag_field: Option<usize>,
+    start: TokenStream2,
+    errors: &mut Vec<TokenStream2>,
+) -> TokenStream2 {
+    let mut bounds = start;
+    let (struct_before, struct_after) = build_pad(&context.self_attrs);
+    bounds = quote! { #bounds #struct_before };
+    for (i, f) in fields.iter().enumerate() {
+        let (context, attr_errors) =
+            context.recurse_into(Level::Field, crate::field_name(&f.ident, i), &f.attrs);
+        errors.push(attr_errors);
+        if tag_field == Some(i) {
+            continue;
+        }
+        let self_attrs = &context.self_attrs;
+        let value = if self_attrs.rest {
+            quote! { _binary::SizeBounds::ANY }
+        } else {
+            value_bounds(&context, generics, f)
+        };
+        let before = self_attrs.pad_before.map(|n| {
+            let n = n as usize;
+            quote! { .then(_binary::SizeBounds::exact(#n)) }
+        });
+        let align = build_align(self_attrs);
+        let after = self_attrs.pad_after.map(|n| {
+            let n = n as usize;
+            quote! { .then(_binary::SizeBounds::exact(#n)) }
+        });
+        bounds = quote! { #bounds #before #align .then(#value) #after };
+    }
+    quote! { #bounds #struct_after }
+}
+
+// The padding written around a struct or variant, as methods applied to its bounds; the alignment
+// comes last, as in build_pad_encode
+fn build_pad(self_attrs: &SelfAttrs) -> (TokenStream2, TokenStream2) {
+    let before = self_attrs.pad_before.map(|n| {
+        let n = n as usize;
+        quote! { .then(_binary::SizeBounds::exact(#n)) }
+    });
+    let after = self_attrs.pad_after.map(|n| {
+        let n = n as usize;
+        quote! { .then(_binary::SizeBounds::exact(#n)) }
+    });
+    let align = build_align(self_attrs);
+    (quote! { #before }, quote! { #after #align })
+}
+
+// The alignment of a field or struct, as a method applied to its bounds
+fn build_align(self_attrs: &SelfAttrs) -> Option<TokenStream2> {
+    self_attrs.align.map(|n| {
+        let n = n as usize;
+        if self_attrs.align_stream {
+            quote! { .align_stream(#n) }
+        } else {
+            quote! { .align(#n) }
+        }
+    })
+}
+
+// The bounds of a field's value, as it is written by encode_value
+fn value_bounds(context: &Context, generics: &mut Generics, f: &Field) -> TokenStream2 {
+    let self_attrs = &context.self_attrs;
+    let value = if self_attrs.serialize_with.is_some()
+        || self_attrs.deserialize_with.is_some()
+        || self_attrs.map.is_some()
+    {
+        quote! { _binary::SizeBounds::ANY }
+    } else {
+        let len = self_attrs.len.flatten().map_or(0, |ty| ty.size());
+        let ty = crate::value_type(context, &f.ty);
+        let types: Vec<Type> = match (&self_attrs.into, &self_attrs.from) {
+            (None, None) => vec![ty],
+            (Some((into, _)), None) => vec![into.clone(), ty],
+            (None, Some((from, _))) => vec![ty, from.clone()],
+            (Some((into, _)), Some((from, _))) => vec![into.clone(), from.clone()],
+        };
+        let mut value = quote! {};
+        for ty in types {
+            generics
+                .make_where_clause()
+                .predicates
+                .push(crate::make_generic_bound(
+                    ty.clone(),
+                    parse_quote! {_binary::BinSize},
+                ));
+            let bounds = quote! { _binary::SizeBounds::of::<#ty>(#len) };
+            value = if value.is_empty() {
+                bounds
+            } else {
+                quote! { #value.or(#bounds) }
+            };
+        }
+        if self_attrs.tag_expr.is_some() {
+            // The value is written without its tag
+            let ty = crate::value_type(context, &f.ty);
+            let tag: Type = parse_quote! { <#ty as _binary::BinSerializeTagged>::Tag };
+            let predicates = &mut generics.make_where_clause().predicates;
+            predicates.push(crate::make_generic_bound(
+                ty,
+                parse_quote! {_binary::BinSerializeTagged},
+            ));
+            predicates.push(crate::make_generic_bound(
+                tag.clone(),
+                parse_quote! {_binary::BinSize},
+            ));
+            quote! { #value.without(_binary::SizeBounds::of::<#tag>(0)) }
+        } else {
+            value
+        }
+    };
+    let value = build_nest(self_attrs, value);
+    if self_attrs.is_optional() {
+        quote! { #value.optional() }
+    } else {
+        value
+    }
+}
+
+// Bounds of a value written within a length-prefixed buffer, if nested
+fn build_nest(self_attrs: &SelfAttrs, value: TokenStream2) -> TokenStream2 {
+    if !self_attrs.nest {
+        return value;
+    }
+    let len = self_attrs.nest_ty.unwrap().size();
+    quote! { _binary::SizeBounds::exact(#len).then(#value) }
+}
@@ -0,0 +1,252 @@
+use syn::export::TokenStream2;
+use syn::spanned::Spanned;
+use syn::{Data, DeriveInput, Field, Fields, Ident};
+
+use crate::context::{Context, Level};
+use crate::SelfAttrs;
+
+// Whether the attributes change how a value is (de)serialized beyond its byte order and position,
+// which a view reading it in place cannot follow
+fn unsupported(self_attrs: &SelfAttrs) -> bool {
+    self_attrs.is_optional()
+        || self_attrs.flags
+        || self_attrs.nest
+        || self_attrs.nest_variants
+        || self_attrs.rest
+        || self_attrs.serialize_with.is_some()
+        || self_attrs.deserialize_with.is_some()
+        || self_attrs.args.is_some()
+        || self_attrs.tag_expr.is_some()
+        || self_attrs.map.is_some()
+        || self_attrs.from.is_some()
+        || self_attrs.into.is_some()
+        || !self_attrs.imports.is_empty()
+        || !self_attrs.asserts.is_empty()
+        || self_attrs.validate.is_some()
+        || self_attrs.align_stream
+}
+
+// The views' own methods, which no field's getter may share a name with
+const METHODS: &[&str] = &["new", "as_bytes", "get", "as_view", "set"];
+
+// Why a getter for the field `name` would clash with another method of the views, if it would
+fn name_collision(name: &str, fields: &[&Field]) -> Option<String> {
+    if METHODS.contains(&name) {
+        return Some(format!(
+            "BinView cannot make a getter for field `{}`, as the views have a method of that name",
+            name
+        ));
+    }
+    let setter_of = name.strip_prefix("set_")?;
+    fields
+        .iter()
+        .find(|f| f.ident.as_ref().is_some_and(|i| i == setter_of))
+        .map(|_| {
+            format!(
+                "BinView cannot make a getter for field `{}`, which is the name of field `{}`'s setter",
+                name, setter_of
+            )
+        })
+}
+
+// The expression `offset`, advanced to the next multiple of `align`
+fn build_align(offset: TokenStream2, align: Option<u64>) -> TokenStream2 {
+    match align {
+        Some(n) => {
+            let n = n as usize;
+            quote! { {
+                let offset = #offset;
+                offset + (#n - offset % #n) % #n
+            } }
+        }
+        None => offset,
+    }
+}
+
+// Builds FooView<'a> and FooViewMut<'a> for a fixed-layout struct Foo, reading and writing each
+// field in place at the offset it is (de)serialized at
+pub(crate) fn derive(input: DeriveInput) -> TokenStream2 {
+    let (context, attr_errors) = Context::from_input(&input);
+    let ident = &input.ident;
+    let vis = &input.vis;
+    let view = Ident::new(&format!("{}View", ident), ident.span());
+    let view_mut = Ident::new(&format!("{}ViewMut", ident), ident.span());
+
+    let fields = match &input.data {
+        Data::Struct(s) => match &s.fields {
+            Fields::Named(f) => f.named.iter().collect::<Vec<_>>(),
+            _ => vec![],
+        },
+        _ => vec![],
+    };
+    let mut errors = vec![attr_errors];
+    if fields.is_empty() {
+        let span = ident.span();
+        errors.push(quote_spanned! {span=>
+            compile_error!("BinView can only be derived for structs with named fields");
+        });
+    }
+    if !input.generics.params.is_empty() {
+        let span = input.generics.span();
+        errors.push(quote_spanned! {span=>
+            compile_error!("BinView cannot be derived for generic types, whose layout is not fixed");
+        });
+    }
+    if unsupported(&context.self_attrs) {
+        let span = ident.span();
+        errors.push(quote_spanned! {span=>
+            compile_error!("BinView only supports the attributes little, big, reset, pad_before, pad_after and align = N, which keep a struct's layout fixed");
+        });
+    }
+
+    let mut consts = vec![];
+    let mut getters = vec![];
+    let mut setters = vec![];
+    let mut getters_mut = vec![];
+    let mut reads = vec![];
+    let mut writes = vec![];
+    let top = &context.self_attrs;
+    let pad_before = top.pad_before.unwrap_or(0) as usize;
+    let mut end = quote! { #pad_before };
+    for (i, f) in fields.iter().enumerate() {
+        let (context, attr_errors) = context.recurse_into(
+            Level::Field,
+            f.ident.as_ref().unwrap().to_string(),
+            &f.attrs,
+        );
+        errors.push(attr_errors);
+        let self_attrs = &context.self_attrs;
+        if unsupported(self_attrs) {
+            let span = f.span();
+            errors.push(quote_spanned! {span=>
+                compile_error!("BinView only supports the attributes little, big, reset, pad_before, pad_after and align = N, which keep a field at a fixed offset");
+            });
+        }
+
+        let name = f.ident.as_ref().unwrap();
+        let setter = Ident::new(&format!("set_{}", name), name.span());
+        let ty = &f.ty;
+        let offset = Ident::new(&format!("OFFSET_{}", i), name.span());
+        let before = self_attrs.pad_before.unwrap_or(0) as usize;
+        let after = self_attrs.pad_after.unwrap_or(0) as usize;
+        let start = build_align(quote! { #end + #before }, self_attrs.align);
+        consts.push(quote! {
+            const #offset: usize = #start;
+        });
+        end = quote! { #offset + <#ty as _binary::BinViewField>::SIZE + #after };
+
+        let range = quote! { #offset..#offset + <#ty as _binary::BinViewField>::SIZE };
+        let attrs = context.build_attrs();
+        reads.push(quote! {
+            #name: <#ty as _binary::BinViewField>::read_view(&bytes[#range], #attrs),
+        });
+        writes.push(quote! {
+            _binary::BinViewField::write_view(&self.#name, &mut bytes[#range], #attrs);
+        });
+        if let Some(message) = name_collision(&name.to_string(), &fields) {
+            let span = name.span();
+            errors.push(quote_spanned! {span=>
+                compile_error!(#message);
+            });
+            continue;
+        }
+        getters.push(quote! {
+            #vis fn #name(&self) -> #ty {
+                let attrs = _binary::attr::Attrs::zero();
+                <#ty as _binary::BinViewField>::read_view(&self.buf[#range], #attrs)
+            }
+        });
+        getters_mut.push(quote! {
+            #vis fn #name(&self) -> #ty {
+                self.as_view().#name()
+            }
+        });
+        setters.push(quote! {
+            #vis fn #setter(&mut self, value: #ty) {
+                let attrs = _binary::attr::Attrs::zero();
+                _binary::BinViewField::write_view(&value, &mut self.buf[#range], #attrs);
+            }
+        });
+    }
+    let pad_after = top.pad_after.unwrap_or(0) as usize;
+    let size = build_align(quote! { #end + #pad_after }, top.align);
+
+    let imp = quote! {
+        impl _binary::BinViewField for #ident {
+            const SIZE: usize = #size;
+            fn read_view(bytes: &[u8], attrs: _binary::attr::Attrs) -> Self {
+                Self {
+                    #(#reads)*
+                }
+            }
+            fn write_view(&self, bytes: &mut [u8], attrs: _binary::attr::Attrs) {
+                #(#writes)*
+            }
+        }
+
+        #(#consts)*
+
+        impl<'a> #view<'a> {
+            // Fails with InsufficientData if `buf` is too short to hold the struct; any bytes after
+            // it are left out of the view
+            #vis fn new(buf: &'a [u8]) -> _binary::Result<Self> {
+                let size = <#ident as _binary::BinViewField>::SIZE;
+                if buf.len() < size {
+                    return Err(_binary::BinError::InsufficientData);
+                }
+                Ok(Self { buf: &buf[..size] })
+            }
+
+            #vis fn as_bytes(&self) -> &'a [u8] {
+                self.buf
+            }
+
+            // Copies every field out of the buffer
+            #vis fn get(&self) -> #ident {
+                _binary::BinViewField::read_view(self.buf, _binary::attr::Attrs::zero())
+            }
+
+            #(#getters)*
+        }
+
+        impl<'a> #view_mut<'a> {
+            // As the view's new, for a buffer that may be written to
+            #vis fn new(buf: &'a mut [u8]) -> _binary::Result<Self> {
+                let size = <#ident as _binary::BinViewField>::SIZE;
+                if buf.len() < size {
+                    return Err(_binary::BinError::InsufficientData);
+                }
+                Ok(Self { buf: &mut buf[..size] })
+            }
+
+            #vis fn as_view(&self) -> #view<'_> {
+                #view { buf: self.buf }
+            }
+
+            #vis fn as_bytes(&mut self) -> &mut [u8] {
+                self.buf
+            }
+
+            // Writes every field into the buffer, leaving any padding as it is
+            #vis fn set(&mut self, value: &#ident) {
+                _binary::BinViewField::write_view(value, self.buf, _binary::attr::Attrs::zero());
+            }
+
+            #(#getters_mut)*
+            #(#setters)*
+        }
+    };
+    let errors = quote! { #(#errors)* };
+    let wrapped = crate::wrap_impl(&context.self_attrs.krate, imp, errors);
+    quote! {
+        #vis struct #view<'a> {
+            buf: &'a [u8],
+        }
+
+        #vis struct #view_mut<'a> {
+            buf: &'a mut [u8],
+        }
+
+        #wrapped
+    }
+}
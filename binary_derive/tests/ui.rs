@@ -0,0 +1,9 @@
+// Compile-fail tests for the diagnostics of #[binary(...)], checked against the errors recorded in
+// each case's .stderr file, each paired with a case of the same name that compiles. Run with
+// TRYBUILD=overwrite to record the errors again after changing them.
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/fail/*.rs");
+    t.pass("tests/ui/pass/*.rs");
+}
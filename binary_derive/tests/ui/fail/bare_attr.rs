@@ -0,0 +1,9 @@
+use binary::{BinDeserialize, BinSerialize};
+
+#[derive(BinSerialize, BinDeserialize)]
+#[binary]
+struct S {
+    a: u8,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use binary::{BinDeserialize, BinSerialize};
+
+#[derive(BinSerialize, BinDeserialize)]
+struct S {
+    #[binary(default)]
+    a: u8,
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use binary::{BinDeserialize, BinSerialize};
+
+#[derive(BinSerialize, BinDeserialize)]
+struct S {
+    #[binary(flags)]
+    flags: u8,
+    #[binary(flags(0x03))]
+    a: Option<u8>,
+    #[binary(flags(0x02))]
+    b: Option<u8>,
+}
+
+fn main() {}
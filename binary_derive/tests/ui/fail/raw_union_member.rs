@@ -0,0 +1,11 @@
+use binary::{BinDeserialize, BinSerialize};
+
+#[derive(BinSerialize, BinDeserialize, Clone, Copy)]
+#[repr(C)]
+#[binary(raw)]
+union U {
+    byte: u8,
+    flag: bool,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use binary::{BinDeserialize, BinSerialize};
+
+#[derive(BinSerialize, BinDeserialize, Clone, Copy)]
+#[binary(raw)]
+union U {
+    bytes: [u8; 4],
+    word: u32,
+}
+
+fn main() {}
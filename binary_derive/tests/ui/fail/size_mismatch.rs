@@ -0,0 +1,10 @@
+use binary::BinSize;
+
+#[derive(BinSize)]
+#[binary(size = 4)]
+struct S {
+    a: u8,
+    b: u16,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use binary::{BinDeserialize, BinSerialize};
+
+#[derive(BinSerialize, BinDeserialize)]
+#[binary(tag(u8))]
+enum E {
+    #[binary(tag = 256)]
+    A,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use binary::{BinDeserialize, BinSerialize};
+
+#[derive(BinSerialize, BinDeserialize)]
+struct S {
+    #[binary(flags(0x01))]
+    a: Option<u8>,
+    #[binary(flags)]
+    flags: u8,
+    b: u8,
+}
+
+fn main() {}
@@ -0,0 +1,18 @@
+use binary::{BinDeserialize, BinSerialize};
+
+fn check(_: &U) -> Result<(), String> {
+    Ok(())
+}
+
+#[derive(BinSerialize, BinDeserialize, Clone, Copy)]
+#[binary(import = "kind: u8", unsafe_select = "kind", validate = "check")]
+union U {
+    #[binary(tag = 1, pad_before = 2, align = 4)]
+    word: u32,
+    #[binary(tag = 2, if = "kind == 2", assert("self.short > 0"))]
+    short: u16,
+    #[binary(tag = 3, flags)]
+    flags: u8,
+}
+
+fn main() {}
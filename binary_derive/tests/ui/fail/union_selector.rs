@@ -0,0 +1,12 @@
+use binary::{BinDeserialize, BinSerialize};
+
+#[derive(BinSerialize, BinDeserialize, Clone, Copy)]
+#[binary(import = "kind: u8", select = "kind")]
+union U {
+    #[binary(tag = 1)]
+    word: u32,
+    #[binary(tag = 2)]
+    short: u16,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use binary::{BinDeserialize, BinSerialize};
+
+#[derive(BinSerialize, BinDeserialize)]
+struct S {
+    #[binary(lenn(u8))]
+    a: Vec<u8>,
+}
+
+fn main() {}
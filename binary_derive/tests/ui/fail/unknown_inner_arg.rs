@@ -0,0 +1,9 @@
+use binary::{BinDeserialize, BinSerialize};
+
+#[derive(BinSerialize, BinDeserialize)]
+struct S {
+    #[binary(inner(bigg))]
+    a: Vec<u16>,
+}
+
+fn main() {}
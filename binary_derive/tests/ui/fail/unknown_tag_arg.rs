@@ -0,0 +1,9 @@
+use binary::{BinDeserialize, BinSerialize};
+
+#[derive(BinSerialize, BinDeserialize)]
+#[binary(tag(u7))]
+enum E {
+    A,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use binary::BinView;
+
+#[derive(BinView)]
+struct S {
+    a: u8,
+    #[binary(map = "u8::from")]
+    b: u8,
+}
+
+fn main() {}
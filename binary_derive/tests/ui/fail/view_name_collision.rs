@@ -0,0 +1,10 @@
+use binary::BinView;
+
+#[derive(BinView)]
+struct Header {
+    get: u8,
+    len: u16,
+    set_len: u16,
+}
+
+fn main() {}
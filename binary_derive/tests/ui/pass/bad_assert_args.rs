@@ -0,0 +1,9 @@
+use binary::{BinDeserialize, BinSerialize};
+
+#[derive(BinSerialize, BinDeserialize)]
+#[binary(assert("self.a > 0"))]
+struct S {
+    a: u8,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use binary::{BinDeserialize, BinSerialize};
+
+#[derive(BinSerialize, BinDeserialize)]
+#[binary(tag(u8))]
+enum E {
+    #[binary(tag = 1)]
+    A,
+    #[binary(tag = 2)]
+    B,
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use binary::{BinDeserialize, BinSerialize};
+
+#[derive(BinSerialize, BinDeserialize)]
+#[binary(tag(u8))]
+enum E {
+    #[binary(tag = 1)]
+    A,
+    B,
+    #[binary(tag = 3)]
+    C,
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use binary::{BinDeserialize, BinSerialize};
+
+#[derive(BinSerialize, BinDeserialize, Clone, Copy)]
+#[repr(C)]
+#[binary(raw)]
+union U {
+    bytes: [u8; 4],
+    word: u32,
+}
+
+fn main() {}
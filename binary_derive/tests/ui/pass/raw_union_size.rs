@@ -0,0 +1,11 @@
+use binary::{BinDeserialize, BinSerialize};
+
+#[derive(BinSerialize, BinDeserialize, Clone, Copy)]
+#[repr(C)]
+#[binary(raw)]
+union U {
+    word: u32,
+    shorts: [u16; 2],
+}
+
+fn main() {}
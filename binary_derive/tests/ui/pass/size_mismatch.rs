@@ -0,0 +1,11 @@
+use binary::BinSize;
+
+#[derive(BinSize)]
+#[binary(size = 4)]
+struct S {
+    a: u8,
+    #[binary(align = 2)]
+    b: u16,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use binary::BinSize;
+
+#[derive(BinSize)]
+#[binary(size = 2)]
+struct S {
+    a: u8,
+    b: u8,
+}
+
+fn main() {}
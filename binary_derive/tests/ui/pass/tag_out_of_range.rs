@@ -0,0 +1,10 @@
+use binary::{BinDeserialize, BinSerialize};
+
+#[derive(BinSerialize, BinDeserialize)]
+#[binary(tag(u16))]
+enum E {
+    #[binary(tag = 256)]
+    A,
+}
+
+fn main() {}
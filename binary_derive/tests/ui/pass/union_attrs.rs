@@ -0,0 +1,14 @@
+use binary::{BinDeserialize, BinSerialize};
+
+#[derive(BinSerialize, BinDeserialize, Clone, Copy)]
+#[binary(import = "kind: u8", unsafe_select = "kind")]
+union U {
+    #[binary(tag = 1)]
+    word: u32,
+    #[binary(tag = 2)]
+    short: u16,
+    #[binary(tag = 3)]
+    flags: u8,
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use binary::{BinDeserialize, BinSerialize};
+
+#[derive(BinSerialize, BinDeserialize)]
+struct S {
+    #[binary(flags)]
+    flags: u8,
+    #[binary(flags(mask = 1, eq = 1))]
+    a: Option<u8>,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use binary::BinView;
+
+#[derive(BinView)]
+struct S {
+    a: u8,
+    #[binary(big, align = 2)]
+    b: u16,
+}
+
+fn main() {}
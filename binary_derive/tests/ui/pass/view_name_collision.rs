@@ -0,0 +1,10 @@
+use binary::BinView;
+
+#[derive(BinView)]
+struct Header {
+    kind: u8,
+    len: u16,
+    max_len: u16,
+}
+
+fn main() {}